@@ -0,0 +1,248 @@
+//! Scenes built in code, selected with `--builtin`
+
+use std::sync::Arc;
+
+use crate::{
+    camera::{Background, Camera},
+    color::Color,
+    hit_record::{Hittable, HittableList},
+    instance::Instance,
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal},
+    medium::ConstantMedium,
+    quad::{make_box, Quad},
+    scene::Scene,
+    sphere::Sphere,
+    transform::Transform,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BuiltinScene {
+    ThreeSpheres,
+    CornellBox,
+    CornellSmoke,
+}
+
+impl BuiltinScene {
+    pub const NAMES: &str = "three-spheres, cornell, cornell-smoke";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "three-spheres" => Some(BuiltinScene::ThreeSpheres),
+            "cornell" | "cornell-box" => Some(BuiltinScene::CornellBox),
+            "cornell-smoke" => Some(BuiltinScene::CornellSmoke),
+            _ => None,
+        }
+    }
+
+    pub fn build(self) -> Scene {
+        match self {
+            BuiltinScene::ThreeSpheres => three_spheres(),
+            BuiltinScene::CornellBox => cornell_box(),
+            BuiltinScene::CornellSmoke => cornell_smoke(),
+        }
+    }
+}
+
+/// five spheres on a yellow ground, the scene from the first book
+pub fn three_spheres() -> Scene {
+    let aspect_ratio = 16f32 / 9f32;
+    let image_width = 400u32;
+    // let r = (PI / 4.0).cos();
+
+    let mut camera = Camera::new(
+        aspect_ratio,
+        image_width,
+        20.0,
+        80.0,
+        30,
+        Point3::new(-2.0, 2.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    camera.defocus_angle = 10.0;
+    camera.focus_distance = 3.4;
+
+    // world
+    let mut world = HittableList::new();
+
+    // let mat_left = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+    // let mat_right = Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+    //
+    // world.add(Box::new(Sphere::new(Vec3::new(-r, 0.0, -1.0), r, mat_left)));
+    // world.add(Box::new(Sphere::new(Vec3::new(r, 0.0, -1.0), r, mat_right)));
+
+    let mat_center = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        mat_center,
+    )));
+
+    let mat_left = Arc::new(Dielectric::new(1.5));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.5,
+        mat_left,
+    )));
+
+    let mat_bubble = Arc::new(Dielectric::new(1.0 / 1.5));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.4,
+        mat_bubble,
+    )));
+
+    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
+    world.add(Box::new(Sphere::new(
+        Point3::new(1.0, 0.0, -1.0),
+        0.5,
+        mat_right,
+    )));
+
+    let mat_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        mat_ground,
+    )));
+    Scene {
+        camera,
+        world,
+        lights: HittableList::new(),
+    }
+}
+
+/// The Cornell box as in "Ray Tracing: The Next Week": a 555 unit room with
+/// a red and a green wall, a ceiling light and two rotated white boxes
+pub fn cornell_box() -> Scene {
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let light = Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0))),
+    );
+    let (tall_box, short_box) = cornell_boxes(white.clone());
+    let mut scene = cornell_room(light, white);
+    scene.world.add(Box::new(tall_box));
+    scene.world.add(Box::new(short_box));
+    scene
+}
+
+/// The Cornell box with the two boxes turned into dark smoke and white
+/// fog, under a larger and dimmer light
+pub fn cornell_smoke() -> Scene {
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let light = Quad::new(
+        Point3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0))),
+    );
+    let (tall_box, short_box) = cornell_boxes(white.clone());
+    let mut scene = cornell_room(light, white);
+    scene.world.add(Box::new(ConstantMedium::new(
+        Arc::new(tall_box),
+        0.01,
+        Arc::new(Isotropic::new(Color::zero())),
+    )));
+    scene.world.add(Box::new(ConstantMedium::new(
+        Arc::new(short_box),
+        0.01,
+        Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0))),
+    )));
+    scene
+}
+
+/// camera and the five walls, the light is added between the walls and
+/// the floor and is the only entry of `lights`
+fn cornell_room(light: Quad, white: Arc<dyn Material>) -> Scene {
+    let mut camera = Camera::new(
+        1.0,
+        600,
+        40.0,
+        200.0,
+        50,
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    camera.background = Background::Solid(Color::zero());
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+
+    let mut world = HittableList::new();
+    let mut quad = |q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>| {
+        world.add(Box::new(Quad::new(q, u, v, material)));
+    };
+    let x = Vec3::new(555.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 555.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 555.0);
+    quad(Point3::new(555.0, 0.0, 0.0), y, z, green);
+    quad(Point3::zero(), y, z, red);
+    let light: Arc<dyn Hittable> = Arc::new(light);
+    let mut lights = HittableList::new();
+    lights.add(Box::new(light.clone()));
+    world.add(Box::new(light));
+    let mut quad = |q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>| {
+        world.add(Box::new(Quad::new(q, u, v, material)));
+    };
+    quad(Point3::zero(), x, z, white.clone()); // floor
+    quad(Point3::new(555.0, 555.0, 555.0), -x, -z, white.clone()); // ceiling
+    quad(Point3::new(0.0, 0.0, 555.0), x, y, white); // back
+    Scene {
+        camera,
+        world,
+        lights,
+    }
+}
+
+/// the tall and the short box, rotated and placed in the room
+fn cornell_boxes(material: Arc<dyn Material>) -> (Instance, Instance) {
+    let tall_box = make_box(
+        Point3::zero(),
+        Point3::new(165.0, 330.0, 165.0),
+        material.clone(),
+    );
+    let short_box = make_box(Point3::zero(), Point3::new(165.0, 165.0, 165.0), material);
+    (
+        Instance::new(
+            Arc::new(tall_box),
+            Transform::identity()
+                .rotate_y(15.0)
+                .translate(Vec3::new(265.0, 0.0, 295.0)),
+        ),
+        Instance::new(
+            Arc::new(short_box),
+            Transform::identity()
+                .rotate_y(-18.0)
+                .translate(Vec3::new(130.0, 0.0, 65.0)),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hit_record::Hittable, interval::Interval, ray::Ray, utils::Rng};
+
+    #[test]
+    fn cornell_box_is_closed_around_the_boxes() {
+        let Scene { world, .. } = cornell_box();
+        // the side facing the camera is open, every other ray from inside
+        // the room hits a wall, a box or the light
+        let origin = Point3::new(278.0, 400.0, 100.0);
+        let mut rng = Rng::new(1);
+        for i in 0..200 {
+            let mut direction = Vec3::random_range(&mut rng, -1.0, 1.0);
+            direction.z = direction.z.abs();
+            let hit = world.hit(
+                &Ray::new(origin, direction),
+                Interval::new(0.001, f32::INFINITY),
+            );
+            assert!(hit.is_some(), "ray {i} {direction:?} escaped");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit_record::{HitRecord, Hittable, HittableList},
+    interval::Interval,
+    ray::Ray,
+};
+
+/// Bounding volume hierarchy
+///
+/// Objects are split at the median centroid along the longest axis of the
+/// centroid bounds, so each level halves the number of candidates.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> Self {
+        let objects: Vec<Arc<dyn Hittable>> = list.objects.into_iter().map(Arc::from).collect();
+        Self::from_objects(objects)
+    }
+
+    pub fn from_objects(mut objects: Vec<Arc<dyn Hittable>>) -> Self {
+        assert!(!objects.is_empty(), "BvhNode needs at least one object");
+
+        let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>) = match objects.len() {
+            1 => (objects[0].clone(), objects[0].clone()),
+            2 => (objects[0].clone(), objects[1].clone()),
+            len => {
+                let centroid_bounds = objects
+                    .iter()
+                    .map(|o| o.bounding_box().centroid())
+                    .fold(Aabb::empty(), |acc, c| {
+                        Aabb::enclosing(acc, Aabb::from_points(c, c))
+                    });
+                let axis = centroid_bounds.longest_axis();
+
+                objects.sort_by(|a, b| {
+                    let ca = a.bounding_box().centroid()[axis];
+                    let cb = b.bounding_box().centroid()[axis];
+                    ca.total_cmp(&cb)
+                });
+
+                let rest = objects.split_off(len / 2);
+                (
+                    Arc::new(Self::from_objects(objects)),
+                    Arc::new(Self::from_objects(rest)),
+                )
+            }
+        };
+
+        let bbox = Aabb::enclosing(left.bounding_box(), right.bounding_box());
+        Self { left, right, bbox }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(r, interval) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(r, interval);
+        let right_max = hit_left.as_ref().map_or(interval.max, |rec| rec.t);
+        let hit_right = self.right.hit(r, Interval::new(interval.min, right_max));
+
+        hit_right.or(hit_left)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        material::Lambertian,
+        sphere::Sphere,
+        utils::Rng,
+        vec3::{unit_vector, Point3, Vec3},
+    };
+
+    fn random_spheres(count: usize, rng: &mut Rng) -> Vec<(Point3, f32)> {
+        (0..count)
+            .map(|_| {
+                (
+                    Vec3::random_range(rng, -10.0, 10.0),
+                    rng.f32_range(0.1, 1.5),
+                )
+            })
+            .collect()
+    }
+
+    fn build_list(spheres: &[(Point3, f32)]) -> HittableList {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        for (center, radius) in spheres {
+            world.add(Box::new(Sphere::new(*center, *radius, material.clone())));
+        }
+        world
+    }
+
+    #[test]
+    fn bvh_matches_flat_list_nearest_hit() {
+        let mut rng = Rng::new(1);
+        let spheres = random_spheres(500, &mut rng);
+        let flat = build_list(&spheres);
+        let bvh = BvhNode::new(build_list(&spheres));
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Vec3::random_range(&mut rng, -15.0, 15.0);
+            let direction = unit_vector(Vec3::random_range(&mut rng, -1.0, 1.0));
+            let ray = Ray::new(origin, direction);
+            let interval = Interval::new(0.001, f32::INFINITY);
+
+            // nearest of the objects tested one by one
+            let expected = flat
+                .objects
+                .iter()
+                .filter_map(|object| object.hit(&ray, interval))
+                .min_by(|a, b| a.t.total_cmp(&b.t));
+            let actual = bvh.hit(&ray, interval);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    hits += 1;
+                    assert!((e.t - a.t).abs() < 1e-4, "t mismatch: {} vs {}", e.t, a.t);
+                    assert!((e.p - a.p).length() < 1e-3);
+                }
+                (None, None) => {}
+                (e, a) => panic!(
+                    "hit mismatch for {ray:?}: flat={:?} bvh={:?}",
+                    e.map(|r| r.t),
+                    a.map(|r| r.t)
+                ),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn bvh_bounds_enclose_all_objects() {
+        let world = build_list(&random_spheres(64, &mut Rng::new(2)));
+        let expected = world.bounding_box();
+        let bvh = BvhNode::new(world);
+        let actual = bvh.bounding_box();
+        for axis in 0..3 {
+            assert_eq!(
+                expected.axis_interval(axis).min,
+                actual.axis_interval(axis).min
+            );
+            assert_eq!(
+                expected.axis_interval(axis).max,
+                actual.axis_interval(axis).max
+            );
+        }
+    }
+}
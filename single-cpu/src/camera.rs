@@ -1,32 +1,131 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
 use crate::{
-    color::{write_color, Color},
-    hit_record::{self, Hittable, HittableList},
+    color::Color,
+    film::Film,
+    filter::Filter,
+    hit_record::{HitRecord, Hittable, HittableList},
     interval::Interval,
+    material::Material,
     ray::Ray,
-    utils::f32_random,
-    vec3::{
-        random_in_unit_disk, random_on_hemisphere, random_unit_vector, unit_vector, Point3, Vec3,
-    },
+    sampler::{Sampler, SamplerKind},
+    utils::{hash, mix_seed},
+    vec3::{sample_unit_disk, unit_vector, Point3, Vec3},
 };
 
+const TILE_SIZE: u32 = 16;
+
+/// radiance of rays that leave the scene
+#[derive(Clone, Copy, Debug)]
+pub enum Background {
+    /// white to light blue gradient along y
+    Sky,
+    Solid(Color),
+}
+
+impl Background {
+    fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = unit_vector(ray.direction);
+                let a = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+/// Weighs light samples against scattered rays that hit the same light
+/// (multiple importance sampling, Veach)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MisHeuristic {
+    /// pdf / (pdf + other)
+    Balance,
+    /// pdf^2 / (pdf^2 + other^2), closer to picking the better strategy
+    Power,
+}
+
+impl MisHeuristic {
+    pub const NAMES: &str = "balance, power";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "balance" => Some(MisHeuristic::Balance),
+            "power" => Some(MisHeuristic::Power),
+            _ => None,
+        }
+    }
+
+    /// weight of a sample drawn with `pdf` when the other strategy could
+    /// have drawn it with `other`
+    fn weight(&self, pdf: f32, other: f32) -> f32 {
+        if other <= 0.0 {
+            return 1.0;
+        }
+        match self {
+            MisHeuristic::Balance => pdf / (pdf + other),
+            MisHeuristic::Power => pdf * pdf / (pdf * pdf + other * other),
+        }
+    }
+}
+
+/// Stops sampling pixels once their mean has converged
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// samples every pixel gets, later ones come in batches of this size
+    pub min_samples: u32,
+    /// relative standard error of the pixel luminance to stop at
+    pub max_error: f32,
+}
+
+impl AdaptiveSampling {
+    pub const DEFAULT_MIN_SAMPLES: u32 = 16;
+}
+
 pub struct Camera {
     pub aspect_ratio: f32,
     pub image_width: u32,
+    /// with `adaptive` sampling the most a pixel gets
     pub samples_per_pixel: f32,
+    pub adaptive: Option<AdaptiveSampling>,
+    /// safety cap on bounces, Russian roulette usually ends paths first
+    pub max_depth: u32,
+    /// bounces traced before Russian roulette may end a path
+    pub roulette_depth: u32,
     pub vfov: f32, // angle
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
     pub defocus_angle: f32,
     pub focus_distance: f32,
+    /// black lets emissive materials light the scene on their own
+    pub background: Background,
+    /// worker thread count, 0 uses every available core
+    pub threads: usize,
+    /// base seed, every pixel sample derives its random numbers from it
+    pub seed: u64,
+    /// rays get a random time in [shutter_open, shutter_close]. Moving
+    /// objects travel over time 0..1
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+    pub mis: MisHeuristic,
+    /// where pixel, lens, time and BSDF samples come from
+    pub sampler: SamplerKind,
+    /// how each sample is weighted into the pixels around it
+    pub filter: Filter,
 
     image_height: u32,
     center: Point3,
     pixel00_location: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
-    pixel_samples_scale: f32,
-    max_depth: u32,
     u: Vec3,
     v: Vec3,
     w: Vec3,
@@ -35,6 +134,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f32,
         image_width: u32,
@@ -49,19 +149,28 @@ impl Camera {
             aspect_ratio,
             image_width,
             samples_per_pixel,
+            adaptive: None,
             vfov,
             lookfrom,
             lookat,
             vup,
             defocus_angle: 0.0,
             focus_distance: 10.0,
+            background: Background::Sky,
+            threads: 0,
+            seed: 5464,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            mis: MisHeuristic::Power,
+            sampler: SamplerKind::Sobol,
+            filter: Filter::default(),
             image_height: 0,
             center: Point3::zero(),
             pixel00_location: Point3::zero(),
             pixel_delta_u: Vec3::zero(),
             pixel_delta_v: Vec3::zero(),
-            pixel_samples_scale: 0.0,
             max_depth,
+            roulette_depth: 3,
             v: Vec3::zero(),
             u: Vec3::zero(),
             w: Vec3::zero(),
@@ -74,19 +183,28 @@ impl Camera {
             aspect_ratio: 1.0,
             image_width: 100,
             samples_per_pixel: 10.0,
+            adaptive: None,
             vfov: 90.0,
             lookfrom: Point3::new(0.0, 0.0, 0.0),
             lookat: Point3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_distance: 10.0,
+            background: Background::Sky,
+            threads: 0,
+            seed: 5464,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            mis: MisHeuristic::Power,
+            sampler: SamplerKind::Sobol,
+            filter: Filter::default(),
             image_height: 0,
             center: Point3::zero(),
             pixel00_location: Point3::zero(),
             pixel_delta_u: Vec3::zero(),
             pixel_delta_v: Vec3::zero(),
-            pixel_samples_scale: 0.0,
             max_depth: 10,
+            roulette_depth: 3,
             v: Vec3::zero(),
             u: Vec3::zero(),
             w: Vec3::zero(),
@@ -95,28 +213,160 @@ impl Camera {
         }
     }
 
-    pub fn render(&mut self, world: &HittableList) {
+    /// 画面を TILE_SIZE 四方のタイルに分けて、スレッドで並列に描画する
+    ///
+    /// 乱数は (seed, pixel, sample 番号, 次元) から決まるので、
+    /// スレッド数や描画順に関係なく同じ結果になる。
+    ///
+    /// `lights` are sampled directly on every non-specular bounce (next
+    /// event estimation) and combined with the scattered rays by `mis`.
+    /// They should also be part of `world`; with an empty list only the
+    /// scattered rays find the lights.
+    ///
+    /// With `adaptive` sampling a pixel stops after any batch that brings
+    /// its error below the limit, the film records how many samples each
+    /// pixel got.
+    pub fn render(&mut self, world: &dyn Hittable, lights: &HittableList) -> Film {
         self.initialize();
 
-        eprintln!("P3");
-        eprintln!("{} {}", self.image_width, self.image_height);
-        eprintln!("255"); // colors range from 0 to 255
-
-        for j in 0..self.image_height {
-            print!("\rScanning line {}/{}", j + 1, self.image_height);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::zero();
-                for _ in 0..self.samples_per_pixel as u32 {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(ray, self.max_depth, world);
+        let tiles_x = self.image_width.div_ceil(TILE_SIZE);
+        let tiles_y = self.image_height.div_ceil(TILE_SIZE);
+        let tile_count = (tiles_x * tiles_y) as usize;
+        let threads = self.thread_count().min(tile_count).max(1);
+
+        let next_tile = AtomicUsize::new(0);
+        let mut film = Film::new(self.image_width, self.image_height);
+        // 隣のタイルと filter の分だけ重なるので、足し算の順番 (= 結果) が
+        // スレッド数で変わらないようにタイル番号順に merge する
+        let mut finished: Vec<Option<Film>> = (0..tile_count).map(|_| None).collect();
+        let mut next_merge = 0;
+        let (sender, receiver) = mpsc::channel::<(usize, Film)>();
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let next_tile = &next_tile;
+                let camera = &*self;
+                scope.spawn(move || loop {
+                    let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile >= tile_count {
+                        break;
+                    }
+                    let tile_film = camera.render_tile(tile, tiles_x, world, lights);
+                    if sender.send((tile, tile_film)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            for (done, (tile, tile_film)) in receiver.iter().enumerate() {
+                // progress goes to stderr, stdout may carry the image
+                eprint!("\rRendering tile {}/{}", done + 1, tile_count);
+
+                finished[tile] = Some(tile_film);
+                while let Some(tile_film) = finished.get_mut(next_merge).and_then(Option::take) {
+                    let (x0, y0, _, _) = self.film_bounds(next_merge, tiles_x);
+                    film.merge(&tile_film, x0, y0);
+                    next_merge += 1;
+                }
+            }
+        });
+        eprint!("\rDone.                           \n");
+
+        film
+    }
+
+    fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            self.threads
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        }
+    }
+
+    /// タイルの範囲 (x0, y0, x1, y1)。x1, y1 は含まない
+    fn tile_bounds(&self, tile: usize, tiles_x: u32) -> (u32, u32, u32, u32) {
+        let x0 = (tile as u32 % tiles_x) * TILE_SIZE;
+        let y0 = (tile as u32 / tiles_x) * TILE_SIZE;
+        let x1 = (x0 + TILE_SIZE).min(self.image_width);
+        let y1 = (y0 + TILE_SIZE).min(self.image_height);
+        (x0, y0, x1, y1)
+    }
+
+    /// the pixels a tile's samples reach: the tile grown by the filter
+    fn film_bounds(&self, tile: usize, tiles_x: u32) -> (u32, u32, u32, u32) {
+        let (x0, y0, x1, y1) = self.tile_bounds(tile, tiles_x);
+        let reach = self.filter.reach();
+        (
+            x0.saturating_sub(reach),
+            y0.saturating_sub(reach),
+            (x1 + reach).min(self.image_width),
+            (y1 + reach).min(self.image_height),
+        )
+    }
+
+    fn render_tile(
+        &self,
+        tile: usize,
+        tiles_x: u32,
+        world: &dyn Hittable,
+        lights: &HittableList,
+    ) -> Film {
+        let spp = self.samples_per_pixel as u32;
+        let mut sampler = self.sampler.build(spp, self.seed);
+        let (batch, max_error) = match self.adaptive {
+            Some(adaptive) => (
+                adaptive.min_samples.clamp(1, spp.max(1)),
+                adaptive.max_error,
+            ),
+            None => (spp, 0.0),
+        };
+
+        let (x0, y0, x1, y1) = self.tile_bounds(tile, tiles_x);
+        let (fx0, fy0, fx1, fy1) = self.film_bounds(tile, tiles_x);
+        let mut tile_film = Film::new(fx1 - fx0, fy1 - fy0);
+        for j in y0..y1 {
+            for i in x0..x1 {
+                for index in 0..spp {
+                    // 1 batch 終わるごとに収束を確かめる
+                    if index >= batch
+                        && index.is_multiple_of(batch)
+                        && tile_film.relative_error(i - fx0, j - fy0) <= max_error
+                    {
+                        break;
+                    }
+                    sampler.start_pixel_sample(i, j, index);
+                    let offset = self.sample_square(sampler.as_mut());
+                    let ray = self
+                        .get_ray(i, j, offset, sampler.as_mut())
+                        .with_stream(hash(&[self.seed, i as u64, j as u64, index as u64]));
+                    let radiance = self.ray_color(
+                        ray,
+                        self.max_depth,
+                        world,
+                        lights,
+                        1.0,
+                        Color::new(1.0, 1.0, 1.0),
+                        sampler.as_mut(),
+                    );
+                    tile_film.add_filtered_sample(
+                        i - fx0,
+                        j - fy0,
+                        [offset.x, offset.y],
+                        radiance,
+                        &self.filter,
+                    );
                 }
-                write_color(pixel_color * self.pixel_samples_scale);
             }
         }
-        print!("\rDone.                           \n");
+        tile_film
     }
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let offset = self.sample_square();
+
+    /// through `offset` from the center of pixel (i, j). Sample dimensions:
+    /// pixel offset, lens (with defocus), time (with an open shutter), then
+    /// the bounces
+    fn get_ray(&self, i: u32, j: u32, offset: Vec3, sampler: &mut dyn Sampler) -> Ray {
         let pixel_sample = self.pixel00_location
             + ((i as f32 + offset.x) * self.pixel_delta_u)
             + ((j as f32 + offset.y) * self.pixel_delta_v);
@@ -124,28 +374,40 @@ impl Camera {
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        // 閉じたシャッターでは乱数を使わない (静止画の結果を変えない)
+        let ray_time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + (self.shutter_close - self.shutter_open) * sampler.get_1d()
+        } else {
+            self.shutter_open
+        };
+        Ray::new(ray_origin, ray_direction).with_time(ray_time)
     }
-    fn sample_square(&self) -> Vec3 {
-        Vec3::new(f32_random() - 0.5, f32_random(), 0.0)
+    /// offset from the pixel center in [-0.5, 0.5)^2
+    fn sample_square(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let [u, v] = sampler.get_2d();
+        Vec3::new(u - 0.5, v - 0.5, 0.0)
     }
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler) -> Point3 {
+        let p = sample_unit_disk(sampler.get_2d());
         self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
 
+    /// height that `image_width` and `aspect_ratio` give, at least 1
+    pub fn image_height(&self) -> u32 {
+        ((self.image_width as f32 / self.aspect_ratio) as u32).max(1)
+    }
+
+    /// width * height, None when it doesn't fit in u32
+    pub fn pixel_count(&self) -> Option<u32> {
+        self.image_width.checked_mul(self.image_height())
+    }
+
     fn initialize(&mut self) {
-        self.image_height = (self.image_width as f32 / self.aspect_ratio) as u32;
-        self.image_height = if self.image_height < 1 {
-            1
-        } else {
-            self.image_height
-        };
+        self.image_height = self.image_height();
         self.center = self.lookfrom;
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel;
 
         // let focal_length = (self.lookfrom - self.lookat).length();
         let theta = self.vfov.to_radians();
@@ -179,30 +441,595 @@ impl Camera {
         self.defocus_disk_v = self.v * defocus_radius;
     }
 
-    fn ray_color(&self, ray: Ray, depth: u32, world: &HittableList) -> Color {
+    /// `emission_weight` is the MIS weight of the light this ray hits, the
+    /// previous bounce may also have sampled it directly. `throughput` is
+    /// the product of the attenuations from the camera up to this ray
+    #[allow(clippy::too_many_arguments)]
+    fn ray_color(
+        &self,
+        ray: Ray,
+        depth: u32,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        emission_weight: f32,
+        throughput: Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
         if depth == 0 {
             return Color::zero();
         }
-        // let mut record = hit_record::HitRecord {
-        //     p: Point3::zero(),
-        //     normal_vec: Vec3::zero(),
-        //     t: 0.0,
-        //     front_face: false,
-        // };
-        if let Some(record) = world.hit(&ray, Interval::new(0.0001, f32::INFINITY)) {
-            if let Some(material) = &record.material {
-                let (scattered, attenuation) = material.scatter(&ray, &record);
-                return attenuation * self.ray_color(scattered, depth - 1, world);
+        let Some(record) = world.hit(&ray, Interval::new(0.0001, f32::INFINITY)) else {
+            return self.background.color(&ray);
+        };
+        let Some(material) = &record.material else {
+            return Color::zero();
+        };
+
+        let emitted = if emission_weight > 0.0 {
+            emission_weight * material.emitted(&ray, &record)
+        } else {
+            Color::zero()
+        };
+        let Some((scattered, attenuation)) = material.scatter(&ray, &record, sampler) else {
+            return emitted;
+        };
+        let scattered = scattered.with_stream(mix_seed(ray.stream, 0));
+
+        // 鏡・ガラス以外では光源も直接サンプルし、散乱した ray と MIS で重み付けする
+        let sample_lights = !lights.objects.is_empty()
+            && material.eval(&ray, &record, scattered.direction).is_some();
+        let (direct, next_weight) = if sample_lights {
+            let bsdf_pdf = material.pdf(&ray, &record, scattered.direction);
+            let light_pdf = lights.pdf_value(record.p, scattered.direction);
+            (
+                self.sample_light(&ray, &record, material.as_ref(), world, lights, sampler),
+                self.mis.weight(bsdf_pdf, light_pdf),
+            )
+        } else {
+            (Color::zero(), 1.0)
+        };
+
+        // Russian roulette: 暗くなった path ほど高い確率で打ち切り、生き残った
+        // path を 1 / survival 倍する。期待値は変わらない
+        let mut attenuation = attenuation;
+        let mut throughput = throughput * attenuation;
+        if self.max_depth - depth >= self.roulette_depth {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+            if sampler.get_1d() >= survival {
+                return emitted + direct;
             }
+            attenuation /= survival;
+            throughput /= survival;
+        }
+        emitted
+            + direct
+            + attenuation
+                * self.ray_color(
+                    scattered,
+                    depth - 1,
+                    world,
+                    lights,
+                    next_weight,
+                    throughput,
+                    sampler,
+                )
+    }
+
+    /// one shadow ray towards a random point on `lights`, MIS weighted
+    /// against scattering into the same direction
+    fn sample_light(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        material: &dyn Material,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let direction = lights.random(record.p, sampler);
+        let pdf = lights.pdf_value(record.p, direction);
+        if pdf <= 0.0 {
+            return Color::zero();
+        }
+        let Some(f) = material.eval(ray, record, direction) else {
             return Color::zero();
-            // let direction = record.normal_vec + random_unit_vector();
-            // // let direction = random_on_hemisphere(record.normal_vec);
-            // return 0.5 * self.ray_color(Ray::new(record.p, direction), depth - 1, world);
+        };
+        // whatever the shadow ray hits first: the light, or an occluder
+        // that emits nothing
+        let shadow_ray = Ray::new(record.p, direction)
+            .with_time(ray.time)
+            .with_stream(mix_seed(ray.stream, 1));
+        let Some(light_record) = world.hit(&shadow_ray, Interval::new(0.0001, f32::INFINITY))
+        else {
+            return Color::zero();
+        };
+        let Some(light_material) = &light_record.material else {
+            return Color::zero();
+        };
+        let weight = self.mis.weight(pdf, material.pdf(ray, record, direction));
+        f * light_material.emitted(&shadow_ray, &light_record) * (weight / pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        filter::FilterKind,
+        hit_record::HittableList,
+        material::{Dielectric, DiffuseLight, Lambertian, Metal},
+        quad::Quad,
+        sampler::IndependentSampler,
+        sphere::Sphere,
+    };
+
+    fn scene() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0))),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5))),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(-1.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(1.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3)),
+        )));
+        world
+    }
+
+    fn render_with_threads(threads: usize, filter: Filter) -> Vec<Color> {
+        let mut camera = Camera::new(
+            16.0 / 9.0,
+            53,
+            40.0,
+            4.0,
+            8,
+            Point3::new(-2.0, 2.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        camera.defocus_angle = 2.0;
+        camera.focus_distance = 3.4;
+        camera.threads = threads;
+        camera.filter = filter;
+        camera
+            .render(&scene(), &HittableList::new())
+            .pixels()
+            .collect()
+    }
+
+    #[test]
+    fn render_is_independent_of_thread_count() {
+        // a wide filter makes neighbouring tiles overlap
+        for filter in [Filter::default(), Filter::new(FilterKind::Mitchell)] {
+            let single = render_with_threads(1, filter);
+            for threads in [2, 3, 8] {
+                let multi = render_with_threads(threads, filter);
+                assert_eq!(single.len(), multi.len());
+                for (a, b) in single.iter().zip(&multi) {
+                    assert_eq!(
+                        (a.x.to_bits(), a.y.to_bits(), a.z.to_bits()),
+                        (b.x.to_bits(), b.y.to_bits(), b.z.to_bits()),
+                        "pixel differs with {threads} threads and {filter:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filters_spread_an_edge_across_the_tile_seam() {
+        // a light filling the left half of the view, its edge falls between
+        // pixels 15 and 16, which is also where two tiles meet
+        let mut world = HittableList::new();
+        world.add(Box::new(Quad::new(
+            Point3::new(-100.0, -100.0, -1.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(0.0, 200.0, 0.0),
+            Arc::new(DiffuseLight::new(Color::new(1.0, 1.0, 1.0))),
+        )));
+        let row = |kind| {
+            let mut camera = Camera::default();
+            camera.aspect_ratio = 1.0;
+            camera.image_width = 32;
+            camera.samples_per_pixel = 64.0;
+            camera.lookfrom = Point3::zero();
+            camera.lookat = Point3::new(0.0, 0.0, -1.0);
+            camera.background = Background::Solid(Color::zero());
+            camera.filter = Filter::new(kind);
+            let film = camera.render(&world, &HittableList::new());
+            (0..32).map(|x| film.pixel(x, 8).x).collect::<Vec<_>>()
+        };
+
+        // the box filter keeps the edge hard
+        let hard = row(FilterKind::Box);
+        assert_eq!((hard[15], hard[16]), (1.0, 0.0));
+
+        // the tent blends the two pixels at the edge, and only those
+        let soft = row(FilterKind::Tent);
+        assert!(0.5 < soft[15] && soft[15] < 0.95, "{}", soft[15]);
+        assert!((soft[15] + soft[16] - 1.0).abs() < 0.05);
+        assert_eq!((soft[14], soft[17]), (1.0, 0.0));
+
+        // Mitchell's negative lobes over- and undershoot a little
+        let sharp = row(FilterKind::Mitchell);
+        assert!(
+            sharp[14] > 1.0 && sharp[17] < 0.0,
+            "{} {}",
+            sharp[14],
+            sharp[17]
+        );
+        assert_eq!((sharp[12], sharp[19]), (1.0, 0.0));
+    }
+
+    #[test]
+    fn pixel_samples_are_centred_on_the_pixel() {
+        let camera = Camera::default();
+        for kind in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let mut sampler = kind.build(256, 1);
+            let mut mean = Vec3::zero();
+            for index in 0..256 {
+                sampler.start_pixel_sample(3, 5, index);
+                let offset = camera.sample_square(sampler.as_mut());
+                assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5);
+                mean += offset / 256.0;
+            }
+            assert!(mean.length() < 0.05, "{kind:?}: {} {}", mean.x, mean.y);
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_where_the_noise_is() {
+        let mut camera = Camera::new(
+            16.0 / 9.0,
+            48,
+            40.0,
+            256.0,
+            8,
+            Point3::new(0.0, 0.5, 3.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        camera.adaptive = Some(AdaptiveSampling {
+            min_samples: 16,
+            max_error: 0.02,
+        });
+        let film = camera.render(&scene(), &HittableList::new());
+
+        // the smooth sky converges right away, the diffuse spheres don't
+        assert_eq!(film.sample_count(0, 0), 16);
+        let (x, y) = (film.width / 2, film.height / 2);
+        assert!(film.sample_count(x, y) > 16, "{}", film.sample_count(x, y));
+        // pixels stop once they are below the error or at the limit
+        for y in 0..film.height {
+            for x in 0..film.width {
+                let count = film.sample_count(x, y);
+                assert!(count.is_multiple_of(16) && (16..=256).contains(&count));
+                assert!(count == 256 || film.relative_error(x, y) <= 0.02);
+            }
+        }
+        assert!(
+            film.mean_sample_count() < 128.0,
+            "{}",
+            film.mean_sample_count()
+        );
+
+        // without it every pixel gets the full count
+        camera.adaptive = None;
+        camera.samples_per_pixel = 4.0;
+        let film = camera.render(&scene(), &HittableList::new());
+        assert!((0..film.width).all(|x| film.sample_count(x, 0) == 4));
+    }
+
+    #[test]
+    fn emission_lights_a_dark_scene() {
+        // camera inside a glowing sphere, looking at a grey ball
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::zero(),
+            10.0,
+            Arc::new(DiffuseLight::new(Color::new(2.0, 1.0, 0.5))),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let mut camera = Camera::default();
+        camera.image_width = 9;
+        camera.samples_per_pixel = 8.0;
+        camera.vfov = 120.0;
+        camera.background = Background::Solid(Color::zero());
+        camera.threads = 1;
+        let film = camera.render(&world, &HittableList::new());
+
+        // the light seen directly from the corner
+        let corner = film.pixel(0, 0);
+        assert_eq!((corner.x, corner.y, corner.z), (2.0, 1.0, 0.5));
+        // the ball reflects half of the light around it, bouncing up to
+        // max_depth times
+        let center = film.pixel(4, 4);
+        assert!(center.x > 0.9 && center.x <= 2.0, "{center:?}");
+        assert!((center.y / center.x - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn moving_light_smears_over_the_shutter() {
+        // a small light sweeping from left to right in front of the camera
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::moving(
+            Point3::new(-2.0, 0.0, -3.0),
+            Point3::new(2.0, 0.0, -3.0),
+            0.5,
+            Arc::new(DiffuseLight::new(Color::new(1.0, 1.0, 1.0))),
+        )));
+
+        let render = |shutter_close: f32| {
+            let mut camera = Camera::default();
+            camera.image_width = 15;
+            camera.samples_per_pixel = 64.0;
+            camera.background = Background::Solid(Color::zero());
+            camera.threads = 1;
+            camera.shutter_close = shutter_close;
+            camera.render(&world, &HittableList::new())
+        };
+
+        // instantaneous: only the start position is lit
+        let still = render(0.0);
+        assert_eq!(still.pixel(7, 7).x, 0.0);
+        assert!(still.pixel(2, 7).x > 0.9);
+
+        // open for the whole move: a dim streak across the middle
+        let blurred = render(1.0);
+        for x in [3, 7, 11] {
+            let streak = blurred.pixel(x, 7).x;
+            assert!(streak > 0.05 && streak < 0.5, "pixel {x}: {streak}");
+        }
+        assert_eq!(blurred.pixel(7, 1).x, 0.0);
+    }
+
+    #[test]
+    fn light_sampling_converges_to_the_same_image_with_less_noise() {
+        // a small light over a grey floor, nothing else lights the scene
+        let mut world = HittableList::new();
+        world.add(Box::new(Quad::new(
+            Point3::new(-5.0, 0.0, -5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-0.25, 2.0, -0.25),
+            Vec3::new(0.5, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Arc::new(DiffuseLight::new(Color::new(20.0, 20.0, 20.0))),
+        ));
+        world.add(Box::new(light.clone()));
+        let mut lights = HittableList::new();
+        lights.add(Box::new(light));
+
+        let render = |lights: &HittableList, spp: f32, seed: u64| -> Vec<f32> {
+            let mut camera = Camera::new(
+                1.0,
+                8,
+                60.0,
+                spp,
+                4,
+                Point3::new(0.0, 1.0, 3.0),
+                Point3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+            camera.background = Background::Solid(Color::zero());
+            // independent samples, the comparison is about light sampling
+            camera.sampler = SamplerKind::Independent;
+            camera.seed = seed;
+            camera.threads = 1;
+            camera
+                .render(&world, lights)
+                .pixels()
+                .map(|c| c.y)
+                .collect()
+        };
+        let mean = |pixels: &[f32]| pixels.iter().sum::<f32>() / pixels.len() as f32;
+        let rms_difference = |a: &[f32], b: &[f32]| {
+            let sum: f32 = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+            (sum / a.len() as f32).sqrt()
+        };
+        let no_lights = HittableList::new();
+
+        let reference = mean(&render(&no_lights, 2048.0, 1));
+        let sampled = mean(&render(&lights, 64.0, 1));
+        assert!(
+            (sampled / reference - 1.0).abs() < 0.05,
+            "{sampled} vs {reference}"
+        );
+
+        let nee_noise = rms_difference(&render(&lights, 16.0, 2), &render(&lights, 16.0, 3));
+        let bsdf_noise = rms_difference(&render(&no_lights, 16.0, 2), &render(&no_lights, 16.0, 3));
+        assert!(nee_noise * 3.0 < bsdf_noise, "{nee_noise} vs {bsdf_noise}");
+    }
+
+    #[test]
+    fn mis_converges_to_the_reference_without_fireflies() {
+        // a 4x4 light two units above a floor, the ray hits the floor under
+        // its center
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-2.0, 2.0, -2.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+        ));
+        let mut lights = HittableList::new();
+        lights.add(Box::new(light.clone()));
+        let floor = |material: Arc<dyn Material>| {
+            let mut world = HittableList::new();
+            world.add(Box::new(Quad::new(
+                Point3::new(-5.0, 0.0, -5.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 10.0),
+                material,
+            )));
+            world.add(Box::new(light.clone()));
+            world
+        };
+        let diffuse = floor(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let glossy = floor(Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.3)));
+
+        // direct light only: the floor, then the light. The mirror direction
+        // of the glossy floor grazes the edge of the light
+        let estimate = |world: &HittableList, lights: &HittableList, mis, samples: u32| {
+            let mut camera = Camera::default();
+            camera.background = Background::Solid(Color::zero());
+            camera.mis = mis;
+            camera.max_depth = 2;
+            let mut sampler = IndependentSampler::new(7);
+            let ray = Ray::new(Point3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+            let values: Vec<f64> = (0..samples)
+                .map(|_| {
+                    camera
+                        .ray_color(
+                            ray,
+                            camera.max_depth,
+                            world,
+                            lights,
+                            1.0,
+                            Color::new(1.0, 1.0, 1.0),
+                            &mut sampler,
+                        )
+                        .y as f64
+                })
+                .collect();
+            let mean = values.iter().sum::<f64>() / samples as f64;
+            let variance =
+                values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / samples as f64;
+            let max = values.iter().copied().fold(0.0, f64::max);
+            (mean, variance, max)
+        };
+        let no_lights = HittableList::new();
+
+        // albedo * Le * the view factor of the square from below its center
+        let corner = |x: f64, y: f64| {
+            let (sx, sy) = ((1.0 + x * x).sqrt(), (1.0 + y * y).sqrt());
+            (x / sx * (y / sx).atan() + y / sy * (x / sy).atan()) / (2.0 * std::f64::consts::PI)
+        };
+        let reference = 0.5 * 4.0 * 4.0 * corner(1.0, 1.0);
+        // scattering alone converges too, slowly on the diffuse floor
+        let (glossy_reference, _, _) = estimate(&glossy, &no_lights, MisHeuristic::Power, 400_000);
+        let (_, bsdf_variance, _) = estimate(&diffuse, &no_lights, MisHeuristic::Power, 20_000);
+
+        for mis in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let (mean, variance, _) = estimate(&diffuse, &lights, mis, 20_000);
+            assert!(
+                (mean / reference - 1.0).abs() < 0.02,
+                "{mis:?}: {mean} vs {reference}"
+            );
+            assert!(
+                variance * 2.0 < bsdf_variance,
+                "{mis:?}: {variance} vs {bsdf_variance}"
+            );
+
+            let (mean, _, max) = estimate(&glossy, &lights, mis, 20_000);
+            assert!(
+                (mean / glossy_reference - 1.0).abs() < 0.03,
+                "{mis:?}: {mean} vs {glossy_reference}"
+            );
+            // no fireflies: light samples the lobe barely covers get little
+            // weight, a sample stays under twice what a mirror reflects
+            assert!(max <= 2.0 * 0.9 * 4.0 + 1e-3, "{mis:?}: {max}");
+        }
+    }
+
+    /// counts the rays traced against `inner`
+    struct Counting {
+        inner: HittableList,
+        rays: AtomicUsize,
+    }
+
+    impl Hittable for Counting {
+        fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+            self.rays.fetch_add(1, Ordering::Relaxed);
+            self.inner.hit(r, interval)
         }
 
-        let unit_direction = unit_vector(ray.direction);
-        let a = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        fn bounding_box(&self) -> crate::aabb::Aabb {
+            self.inner.bounding_box()
+        }
+    }
+
+    #[test]
+    fn russian_roulette_ends_paths_early_without_bias() {
+        // an integrating sphere: a grey room around a spherical lamp. A wall
+        // bounce reaches the lamp with probability (r / R)^2 and the wall
+        // elsewhere, so every wall point has the same radiance
+        // L = albedo * ((r / R)^2 * Le + (1 - (r / R)^2) * L)
+        let (albedo, emit, fraction) = (0.7, 4.0, (2.0f32 / 10.0).powi(2));
+        let expected = albedo * fraction * emit / (1.0 - albedo * (1.0 - fraction));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::zero(),
+            2.0,
+            Arc::new(DiffuseLight::new(Color::new(emit, emit, emit))),
+        ));
+        let mut room = HittableList::new();
+        room.add(Box::new(Sphere::new(
+            Point3::zero(),
+            10.0,
+            Arc::new(Lambertian::new(Color::new(albedo, albedo, albedo))),
+        )));
+        room.add(Box::new(lamp.clone()));
+        let world = Counting {
+            inner: room,
+            rays: AtomicUsize::new(0),
+        };
+        let mut lights = HittableList::new();
+        lights.add(Box::new(lamp));
+
+        let render = |roulette_depth: u32, spp: usize| {
+            let mut camera = Camera::new(
+                1.0,
+                4,
+                30.0,
+                spp as f32,
+                100,
+                Point3::new(0.0, 0.0, 5.0),
+                Point3::new(0.0, 0.0, 10.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+            camera.background = Background::Solid(Color::zero());
+            camera.roulette_depth = roulette_depth;
+            camera.threads = 1;
+            world.rays.store(0, Ordering::Relaxed);
+            let film = camera.render(&world, &lights);
+            let mean = film.pixels().map(|c| c.y).sum::<f32>() / 16.0;
+            (mean, world.rays.load(Ordering::Relaxed) / (16 * spp))
+        };
+
+        // roulette_depth >= max_depth turns it off: paths run until they
+        // hit the lamp or 100 bounces. Rays are counted per path
+        let (full, full_rays) = render(100, 256);
+        let (roulette, roulette_rays) = render(1, 1024);
+        assert!((full / expected - 1.0).abs() < 0.02, "{full} vs {expected}");
+        assert!(
+            (roulette / expected - 1.0).abs() < 0.02,
+            "{roulette} vs {expected}"
+        );
+        assert!(
+            roulette_rays * 5 < full_rays,
+            "{roulette_rays} vs {full_rays}"
+        );
     }
 }
 
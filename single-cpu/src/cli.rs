@@ -0,0 +1,579 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builtin::BuiltinScene,
+    camera::{AdaptiveSampling, Camera, MisHeuristic},
+    filter::{Filter, FilterKind},
+    image::ImageFormat,
+    sampler::SamplerKind,
+    tonemap::{DisplayTransform, ToneMap},
+};
+
+const DEFAULT_OUTPUT: &str = "test.ppm";
+
+pub const USAGE: &str = "\
+Usage: single-cpu [OPTIONS] [SCENE]
+
+Renders SCENE (a TOML scene file) or one of the built-in scenes.
+
+Options:
+  -b, --builtin <NAME>      render a built-in scene instead of a file:
+                            three-spheres, cornell, cornell-smoke (default: three-spheres)
+  -w, --width <PIXELS>      image width, height follows the camera aspect ratio
+  -s, --spp <COUNT>         samples per pixel, the most any pixel gets with
+                            --adaptive
+      --adaptive <ERROR>    stop sampling a pixel once the relative standard
+                            error of its mean drops below ERROR (e.g. 0.02)
+      --min-spp <COUNT>     samples every pixel gets with adaptive sampling,
+                            later ones come in batches of COUNT (default: 16)
+      --heatmap <PATH>      also write the samples per pixel as an image
+  -d, --max-depth <COUNT>   maximum number of bounces per path
+      --roulette-depth <COUNT>
+                            bounces before Russian roulette may end a path
+                            (default: 3)
+      --seed <SEED>         random seed
+      --sampler <NAME>      pixel, lens and BSDF samples: independent,
+                            stratified, halton, sobol, blue-noise
+                            (default: sobol)
+      --filter <NAME>       pixel reconstruction filter: box, tent, gaussian,
+                            mitchell, lanczos (default: box)
+      --filter-radius <PIXELS>
+                            filter radius, at least 0.5 (1 for mitchell)
+                            (default: 0.5 box, 1 tent, 1.5 gaussian,
+                            2 mitchell, 3 lanczos)
+      --mis <HEURISTIC>     weighting of light samples: balance, power
+                            (default: power)
+  -o, --output <PATH>       output image path, `-` for stdout (default: test.ppm)
+  -t, --threads <COUNT>     worker threads, 0 uses every core (default: 0)
+  -f, --format <FORMAT>     image format: ppm, png, png16, hdr, pfm, exr
+                            (default: from --output extension)
+      --tonemap <CURVE>     tone mapping for ppm/png: clamp, reinhard,
+                            reinhard-extended, hable, aces (default: clamp)
+      --white <RADIANCE>    white point of reinhard-extended (default: 4)
+      --exposure <EV>       exposure in stops before tone mapping (default: 0)
+  -h, --help                print this help
+";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub scene: Option<PathBuf>,
+    pub builtin: Option<BuiltinScene>,
+    pub width: Option<u32>,
+    pub samples_per_pixel: Option<u32>,
+    pub adaptive_error: Option<f32>,
+    pub min_samples_per_pixel: Option<u32>,
+    pub heatmap: Option<PathBuf>,
+    pub max_depth: Option<u32>,
+    pub roulette_depth: Option<u32>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub filter: Option<FilterKind>,
+    pub filter_radius: Option<f32>,
+    pub mis: Option<MisHeuristic>,
+    pub output: Option<PathBuf>,
+    pub threads: Option<usize>,
+    pub format: Option<ImageFormat>,
+    pub tone_map: Option<ToneMap>,
+    pub white_point: Option<f32>,
+    pub exposure: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Options),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl Options {
+    /// CLI で指定された値で camera の設定を上書きする。aspect ratio や
+    /// filter の種類は scene 側かもしれないので、画素数と radius はここで
+    /// 確かめる
+    pub fn apply(&self, camera: &mut Camera) -> Result<(), CliError> {
+        if let Some(width) = self.width {
+            camera.image_width = width;
+            if camera.pixel_count().is_none() {
+                return Err(CliError(format!(
+                    "--width {width} makes a {width}x{} image, too many pixels",
+                    camera.image_height()
+                )));
+            }
+        }
+        if let Some(spp) = self.samples_per_pixel {
+            camera.samples_per_pixel = spp as f32;
+        }
+        if let Some(max_error) = self.adaptive_error {
+            let min_samples = camera
+                .adaptive
+                .map_or(AdaptiveSampling::DEFAULT_MIN_SAMPLES, |a| a.min_samples);
+            camera.adaptive = Some(AdaptiveSampling {
+                min_samples,
+                max_error,
+            });
+        }
+        // also applies to adaptive sampling set up by the scene
+        if let (Some(min_samples), Some(adaptive)) =
+            (self.min_samples_per_pixel, &mut camera.adaptive)
+        {
+            adaptive.min_samples = min_samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(roulette_depth) = self.roulette_depth {
+            camera.roulette_depth = roulette_depth;
+        }
+        if let Some(seed) = self.seed {
+            camera.seed = seed;
+        }
+        if let Some(sampler) = self.sampler {
+            camera.sampler = sampler;
+        }
+        if let Some(kind) = self.filter {
+            camera.filter = Filter::new(kind);
+        }
+        if let Some(radius) = self.filter_radius {
+            let kind = camera.filter.kind;
+            if radius < kind.min_radius() {
+                return Err(CliError(format!(
+                    "--filter-radius must be at least {} pixels for the {} filter, got {radius}",
+                    kind.min_radius(),
+                    kind.name()
+                )));
+            }
+            camera.filter.radius = radius;
+        }
+        if let Some(mis) = self.mis {
+            camera.mis = mis;
+        }
+        if let Some(threads) = self.threads {
+            camera.threads = threads;
+        }
+        Ok(())
+    }
+
+    pub fn display_transform(&self) -> DisplayTransform {
+        let mut tone_map = self.tone_map.unwrap_or(ToneMap::Clamp);
+        if let (ToneMap::ReinhardExtended { white }, Some(white_point)) =
+            (&mut tone_map, self.white_point)
+        {
+            *white = white_point;
+        }
+        DisplayTransform {
+            exposure: self.exposure.unwrap_or(0.0),
+            tone_map,
+        }
+    }
+
+    pub fn output_path(&self) -> &Path {
+        self.output.as_deref().unwrap_or(Path::new(DEFAULT_OUTPUT))
+    }
+
+    /// heatmap の形式は拡張子から決める
+    pub fn heatmap(&self) -> Result<Option<(&Path, ImageFormat)>, CliError> {
+        let Some(path) = self.heatmap.as_deref() else {
+            return Ok(None);
+        };
+        match ImageFormat::from_path(path) {
+            Some(format) => Ok(Some((path, format))),
+            None => Err(CliError(format!(
+                "cannot tell the image format of the heatmap `{}`",
+                path.display()
+            ))),
+        }
+    }
+
+    /// --format が無ければ --output の拡張子から決める
+    pub fn image_format(&self) -> Result<ImageFormat, CliError> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        let output = self.output_path();
+        if output == Path::new("-") {
+            return Ok(ImageFormat::Ppm);
+        }
+        ImageFormat::from_path(output).ok_or_else(|| {
+            CliError(format!(
+                "cannot tell the image format from `{}`, use --format",
+                output.display()
+            ))
+        })
+    }
+}
+
+/// 引数 (プログラム名を除く) を解析する
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            if let Some(scene) = &options.scene {
+                return Err(CliError(format!(
+                    "unexpected argument `{arg}`, scene is already `{}`",
+                    scene.display()
+                )));
+            }
+            options.scene = Some(PathBuf::from(arg));
+            continue;
+        }
+
+        // both `--width 400` and `--width=400` are accepted
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError(format!("{name} needs a value")))
+        };
+
+        match name.as_str() {
+            "-b" | "--builtin" => {
+                let scene = value()?;
+                options.builtin = Some(BuiltinScene::from_name(&scene).ok_or_else(|| {
+                    CliError(format!(
+                        "{name}: unknown built-in scene `{scene}`, expected one of {}",
+                        BuiltinScene::NAMES
+                    ))
+                })?);
+            }
+            "-w" | "--width" => options.width = Some(positive(&name, &value()?)?),
+            "-s" | "--spp" => options.samples_per_pixel = Some(positive(&name, &value()?)?),
+            "--adaptive" => options.adaptive_error = Some(positive_f32(&name, &value()?)?),
+            "--min-spp" => {
+                options.min_samples_per_pixel = Some(positive(&name, &value()?)?);
+            }
+            "--heatmap" => options.heatmap = Some(PathBuf::from(value()?)),
+            "-d" | "--max-depth" => options.max_depth = Some(positive(&name, &value()?)?),
+            "--roulette-depth" => options.roulette_depth = Some(positive(&name, &value()?)?),
+            "--seed" => options.seed = Some(number(&name, &value()?)?),
+            "--sampler" => {
+                let sampler = value()?;
+                options.sampler = Some(SamplerKind::from_name(&sampler).ok_or_else(|| {
+                    CliError(format!(
+                        "{name}: unknown sampler `{sampler}`, expected one of {}",
+                        SamplerKind::NAMES
+                    ))
+                })?);
+            }
+            "--filter" => {
+                let filter = value()?;
+                options.filter = Some(FilterKind::from_name(&filter).ok_or_else(|| {
+                    CliError(format!(
+                        "{name}: unknown filter `{filter}`, expected one of {}",
+                        FilterKind::NAMES
+                    ))
+                })?);
+            }
+            "--filter-radius" => options.filter_radius = Some(positive_f32(&name, &value()?)?),
+            "--mis" => {
+                let heuristic = value()?;
+                options.mis = Some(MisHeuristic::from_name(&heuristic).ok_or_else(|| {
+                    CliError(format!(
+                        "{name}: unknown heuristic `{heuristic}`, expected one of {}",
+                        MisHeuristic::NAMES
+                    ))
+                })?);
+            }
+            "-o" | "--output" => options.output = Some(PathBuf::from(value()?)),
+            "-t" | "--threads" => options.threads = Some(number(&name, &value()?)?),
+            "-f" | "--format" => {
+                let format = value()?;
+                options.format =
+                    Some(ImageFormat::from_name(&format).ok_or_else(|| {
+                        CliError(format!("{name}: unknown image format `{format}`"))
+                    })?);
+            }
+            "--tonemap" => {
+                let curve = value()?;
+                options.tone_map = Some(ToneMap::from_name(&curve).ok_or_else(|| {
+                    CliError(format!(
+                        "{name}: unknown tone mapping `{curve}`, expected one of {}",
+                        ToneMap::NAMES
+                    ))
+                })?);
+            }
+            "--white" => options.white_point = Some(positive_f32(&name, &value()?)?),
+            "--exposure" => {
+                let ev = value()?;
+                options.exposure = Some(
+                    ev.parse()
+                        .ok()
+                        .filter(|ev: &f32| ev.is_finite())
+                        .ok_or_else(|| CliError(format!("{name} expects a number, got `{ev}`")))?,
+                );
+            }
+            _ => return Err(CliError(format!("unknown option `{name}`"))),
+        }
+    }
+
+    if let (Some(scene), Some(_)) = (&options.scene, options.builtin) {
+        return Err(CliError(format!(
+            "--builtin cannot be combined with the scene file `{}`",
+            scene.display()
+        )));
+    }
+    if options.white_point.is_some()
+        && !matches!(options.tone_map, Some(ToneMap::ReinhardExtended { .. }))
+    {
+        return Err(CliError(
+            "--white only applies to --tonemap reinhard-extended".to_string(),
+        ));
+    }
+
+    // fail early rather than after rendering
+    options.image_format()?;
+    options.heatmap()?;
+    Ok(Command::Render(options))
+}
+
+fn number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| {
+        CliError(format!(
+            "{name} expects a non-negative integer, got `{value}`"
+        ))
+    })
+}
+
+fn positive(name: &str, value: &str) -> Result<u32, CliError> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(CliError(format!(
+            "{name} expects a positive integer, got `{value}`"
+        ))),
+    }
+}
+
+fn positive_f32(name: &str, value: &str) -> Result<f32, CliError> {
+    match value.parse::<f32>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(CliError(format!(
+            "{name} expects a positive number, got `{value}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let command = parse(&[
+            "scenes/a.toml",
+            "--width",
+            "320",
+            "--spp=16",
+            "--adaptive",
+            "0.05",
+            "--min-spp",
+            "8",
+            "--heatmap",
+            "spp.png",
+            "-d",
+            "8",
+            "--roulette-depth",
+            "4",
+            "--seed",
+            "42",
+            "--sampler",
+            "halton",
+            "--filter",
+            "mitchell",
+            "--filter-radius=1.5",
+            "--mis",
+            "balance",
+            "-o",
+            "out.ppm",
+            "--threads",
+            "0",
+        ])
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Render(Options {
+                scene: Some(PathBuf::from("scenes/a.toml")),
+                builtin: None,
+                width: Some(320),
+                samples_per_pixel: Some(16),
+                adaptive_error: Some(0.05),
+                min_samples_per_pixel: Some(8),
+                heatmap: Some(PathBuf::from("spp.png")),
+                max_depth: Some(8),
+                roulette_depth: Some(4),
+                seed: Some(42),
+                sampler: Some(SamplerKind::Halton),
+                filter: Some(FilterKind::Mitchell),
+                filter_radius: Some(1.5),
+                mis: Some(MisHeuristic::Balance),
+                output: Some(PathBuf::from("out.ppm")),
+                threads: Some(0),
+                format: None,
+                tone_map: None,
+                white_point: None,
+                exposure: None,
+            })
+        );
+        assert_eq!(parse(&["--spp", "4", "--help"]).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn applies_adaptive_sampling() {
+        let options = |args: &[&str]| match parse(args).unwrap() {
+            Command::Render(options) => options,
+            Command::Help => panic!("expected a render command"),
+        };
+        let mut camera = Camera::default();
+        options(&["--min-spp", "8"]).apply(&mut camera).unwrap();
+        assert_eq!(camera.adaptive, None);
+
+        options(&["--adaptive", "0.1"]).apply(&mut camera).unwrap();
+        assert_eq!(
+            camera.adaptive,
+            Some(AdaptiveSampling {
+                min_samples: AdaptiveSampling::DEFAULT_MIN_SAMPLES,
+                max_error: 0.1,
+            })
+        );
+        // --min-spp alone changes adaptive sampling the scene asked for
+        options(&["--min-spp", "8"]).apply(&mut camera).unwrap();
+        assert_eq!(camera.adaptive.unwrap().min_samples, 8);
+    }
+
+    #[test]
+    fn rejects_images_with_too_many_pixels() {
+        let Command::Render(options) = parse(&["--width", "100000"]).unwrap() else {
+            panic!("expected a render command");
+        };
+        let mut camera = Camera::default();
+        camera.aspect_ratio = 16.0 / 9.0;
+        assert_eq!(
+            options.apply(&mut camera).unwrap_err().to_string(),
+            "--width 100000 makes a 100000x56250 image, too many pixels"
+        );
+
+        camera.aspect_ratio = 4.0;
+        options.apply(&mut camera).unwrap();
+        assert_eq!(camera.pixel_count(), Some(100000 * 25000));
+    }
+
+    #[test]
+    fn rejects_filter_radius_below_the_minimum() {
+        let options = |args: &[&str]| match parse(args).unwrap() {
+            Command::Render(options) => options,
+            Command::Help => panic!("expected a render command"),
+        };
+        let mut camera = Camera::default();
+        let err = options(&["--filter-radius", "0.25"])
+            .apply(&mut camera)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--filter-radius must be at least 0.5 pixels for the box filter, got 0.25"
+        );
+
+        // the kind may come from the scene
+        camera.filter = Filter::new(FilterKind::Mitchell);
+        assert!(options(&["--filter-radius", "0.75"])
+            .apply(&mut camera)
+            .is_err());
+        options(&["--filter", "tent", "--filter-radius", "0.75"])
+            .apply(&mut camera)
+            .unwrap();
+        assert_eq!(camera.filter.radius, 0.75);
+    }
+
+    #[test]
+    fn parses_display_transform() {
+        let Command::Render(options) = parse(&[
+            "--tonemap",
+            "reinhard-extended",
+            "--white=8",
+            "--exposure",
+            "-1.5",
+        ])
+        .unwrap() else {
+            panic!("expected a render command");
+        };
+        assert_eq!(
+            options.display_transform(),
+            DisplayTransform {
+                exposure: -1.5,
+                tone_map: ToneMap::ReinhardExtended { white: 8.0 },
+            }
+        );
+        assert_eq!(
+            Options::default().display_transform(),
+            DisplayTransform::default()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let message = |args: &[&str]| parse(args).unwrap_err().to_string();
+        assert_eq!(
+            message(&["--width", "wide"]),
+            "--width expects a positive integer, got `wide`"
+        );
+        assert_eq!(
+            message(&["--spp", "0"]),
+            "--spp expects a positive integer, got `0`"
+        );
+        assert_eq!(message(&["--seed"]), "--seed needs a value");
+        assert_eq!(message(&["--fast"]), "unknown option `--fast`");
+        assert_eq!(
+            message(&["a.toml", "b.toml"]),
+            "unexpected argument `b.toml`, scene is already `a.toml`"
+        );
+        assert_eq!(
+            message(&["--format", "gif"]),
+            "--format: unknown image format `gif`"
+        );
+        assert!(message(&["-o", "image.xyz"]).contains("use --format"));
+        assert!(message(&["--sampler", "sobel"]).starts_with("--sampler: unknown sampler"));
+        assert!(message(&["--filter", "sinc"]).starts_with("--filter: unknown filter `sinc`"));
+        assert!(message(&["--mis", "max"]).starts_with("--mis: unknown heuristic `max`"));
+        assert!(message(&["--tonemap", "sigmoid"]).starts_with("--tonemap: unknown tone mapping"));
+        assert_eq!(
+            message(&["a.toml", "--builtin", "cornell"]),
+            "--builtin cannot be combined with the scene file `a.toml`"
+        );
+        assert!(message(&["-b", "teapot"]).starts_with("-b: unknown built-in scene `teapot`"));
+        assert_eq!(
+            message(&["--exposure", "bright"]),
+            "--exposure expects a number, got `bright`"
+        );
+        assert_eq!(
+            message(&["--adaptive", "0"]),
+            "--adaptive expects a positive number, got `0`"
+        );
+        assert!(message(&["--heatmap", "spp"]).starts_with("cannot tell the image format"));
+        assert_eq!(
+            message(&["--tonemap", "aces", "--white", "2"]),
+            "--white only applies to --tonemap reinhard-extended"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::{self, Write};
+
 use once_cell::sync::Lazy;
 
 use crate::{interval::Interval, vec3::Vec3};
@@ -6,28 +8,72 @@ pub type Color = Vec3;
 
 static INTENSITY: Lazy<Interval> = Lazy::new(|| Interval::new(0.0, 0.999));
 
+/// piecewise sRGB transfer function (IEC 61966-2-1)
 #[inline]
-fn linear_to_gamma(linear_component: f32) -> f32 {
-    if linear_component > 0.0 {
-        linear_component.sqrt()
-    } else {
+pub fn linear_to_srgb(linear_component: f32) -> f32 {
+    if linear_component <= 0.0 {
         0.0
+    } else if linear_component <= 0.0031308 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// inverse of `linear_to_srgb`, for decoding 8/16-bit textures
+#[inline]
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
     }
 }
 
-pub fn write_color(pixel_color: Color) {
-    let mut r = pixel_color.x;
-    let mut g = pixel_color.y;
-    let mut b = pixel_color.z;
+/// sRGB で encode して 8bit に量子化する。入力は tone map 済みの [0, 1]
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
+    let byte = |x: f32| (256f32 * INTENSITY.clamp(linear_to_srgb(x))) as u8;
+    [
+        byte(pixel_color.x),
+        byte(pixel_color.y),
+        byte(pixel_color.z),
+    ]
+}
 
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+/// sRGB で encode して 16bit に量子化する
+pub fn to_rgb16(pixel_color: Color) -> [u16; 3] {
+    let word = |x: f32| (65535f32 * linear_to_srgb(x).clamp(0.0, 1.0)).round() as u16;
+    [
+        word(pixel_color.x),
+        word(pixel_color.y),
+        word(pixel_color.z),
+    ]
+}
 
-    let rbyte = (256f32 * INTENSITY.clamp(r)) as i32;
-    let gbyte = (256f32 * INTENSITY.clamp(g)) as i32;
-    let bbyte = (256f32 * INTENSITY.clamp(b)) as i32;
+pub fn write_color(out: &mut dyn Write, pixel_color: Color) -> io::Result<()> {
+    let [rbyte, gbyte, bbyte] = to_rgb8(pixel_color);
 
-    eprintln!("{rbyte} {gbyte} {bbyte}");
+    writeln!(out, "{rbyte} {gbyte} {bbyte}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_matches_reference_values() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-4);
+        // both pieces meet at the threshold
+        let below = linear_to_srgb(0.0031308);
+        let above = linear_to_srgb(0.0031309);
+        assert!((above - below).abs() < 1e-4);
+        assert_eq!(to_rgb8(Color::new(0.0, 0.5, 1.0)), [0, 188, 255]);
+        for x in [0.001, 0.0031308, 0.18, 0.5, 1.0] {
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-6);
+        }
+        assert_eq!(to_rgb16(Color::new(0.0, 1.0, 2.0)), [0, 65535, 65535]);
+    }
 }
 
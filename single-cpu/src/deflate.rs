@@ -0,0 +1,492 @@
+//! zlib (RFC 1950) / deflate (RFC 1951) compressor and decompressor
+//!
+//! The compressor uses LZ77 with hash chains, emitted as a single block
+//! using the fixed Huffman codes. Not as small as a dynamic-Huffman encoder,
+//! but simple and far smaller than storing rendered images raw.
+//!
+//! The decompressor handles every block type, so PNG textures written by
+//! other tools can be read.
+
+use std::io;
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: usize = 15;
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// LSB-first bit packer
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= value << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored MSB-first
+    fn write_code(&mut self, code: u32, bits: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn write_literal(out: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => out.write_code(0x30 + symbol, 8),
+        144..=255 => out.write_code(0x190 + symbol - 144, 9),
+        256..=279 => out.write_code(symbol - 256, 7),
+        _ => out.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&b| b as usize <= length)
+        .unwrap();
+    write_literal(out, 257 + index as u32);
+    out.write_bits(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index] as u32,
+    );
+
+    let index = DISTANCE_BASE
+        .iter()
+        .rposition(|&b| b as usize <= distance)
+        .unwrap();
+    out.write_code(index as u32, 5);
+    out.write_bits(
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+        DISTANCE_EXTRA[index] as u32,
+    );
+}
+
+#[inline]
+fn hash(data: &[u8], pos: usize) -> usize {
+    let v = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (v.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+}
+
+#[inline]
+fn insert(data: &[u8], head: &mut [usize], prev: &mut [usize], pos: usize) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos;
+    }
+}
+
+/// raw deflate stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::new();
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman)
+    out.write_bits(1, 1);
+    out.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                // the slot may have been reused by a newer position
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut out, best_length, best_distance);
+            for p in pos..pos + best_length {
+                insert(data, &mut head, &mut prev, p);
+            }
+            pos += best_length;
+        } else {
+            write_literal(&mut out, data[pos] as u32);
+            insert(data, &mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+
+    // end of block
+    write_literal(&mut out, 256);
+    out.finish()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// zlib stream: header, deflate data and adler32 checksum
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CM = 8 (deflate), CINFO = 7 (32K window), FLEVEL = 2, FCHECK makes it divisible by 31
+    let mut out = vec![0x78, 0x9c];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// LSB-first bit reader
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> io::Result<u32> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| invalid_data("deflate stream is truncated"))?;
+        let bit = (byte >> (self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit as u32)
+    }
+
+    fn read(&mut self, bits: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for i in 0..bits {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    /// number of codes of each length
+    counts: [u16; 16],
+    /// symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn fixed_literals() -> Self {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        Self::new(&lengths)
+    }
+
+    fn fixed_distances() -> Self {
+        Self::new(&[5; 30])
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> io::Result<u16> {
+        // codes of each length follow those of the previous length
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.bit()? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+/// dynamic block header: code lengths for the literal and distance codes
+fn read_dynamic_codes(bits: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid_data("too many codes in dynamic block"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = bits.read(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (value, repeat) = match code_length_code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *i
+                    .checked_sub(1)
+                    .and_then(|p| lengths.get(p))
+                    .ok_or_else(|| invalid_data("repeat without a previous length"))?;
+                (previous, 3 + bits.read(2)? as usize)
+            }
+            17 => (0, 3 + bits.read(3)? as usize),
+            _ => (0, 11 + bits.read(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid_data("code lengths overflow"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid_data("no end of block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(invalid_data("invalid length symbol"));
+                }
+                let length = LENGTH_BASE[i] as usize + bits.read(LENGTH_EXTRA[i] as u32)? as usize;
+                let d = distances.decode(bits)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(invalid_data("invalid distance symbol"));
+                }
+                let distance =
+                    DISTANCE_BASE[d] as usize + bits.read(DISTANCE_EXTRA[d] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid_data("distance before start of stream"));
+                }
+                // the match may overlap the bytes it produces
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+/// raw deflate stream. Returns the data and the number of bytes consumed
+pub fn inflate(data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+    let mut bits = BitReader { data, pos: 0 };
+    let mut out = vec![];
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align_to_byte();
+                let len = bits.read(16)?;
+                let nlen = bits.read(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid_data("stored block length mismatch"));
+                }
+                let start = bits.pos / 8;
+                let stored = data
+                    .get(start..start + len as usize)
+                    .ok_or_else(|| invalid_data("deflate stream is truncated"))?;
+                out.extend_from_slice(stored);
+                bits.pos += len as usize * 8;
+            }
+            1 => inflate_block(
+                &mut bits,
+                &mut out,
+                &Huffman::fixed_literals(),
+                &Huffman::fixed_distances(),
+            )?,
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(invalid_data("invalid deflate block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    Ok((out, bits.pos.div_ceil(8)))
+}
+
+/// zlib stream with its checksum verified
+pub fn zlib_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 6
+        || !(data[0] as u32 * 256 + data[1] as u32).is_multiple_of(31)
+        || data[0] & 0x0f != 8
+    {
+        return Err(invalid_data("invalid zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid_data("zlib preset dictionaries are not supported"));
+    }
+    let (out, used) = inflate(&data[2..])?;
+    let end = 2 + used;
+    let checksum = data
+        .get(end..end + 4)
+        .ok_or_else(|| invalid_data("zlib stream is missing its checksum"))?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&out) {
+        return Err(invalid_data("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_inflate() {
+        let mut data = b"abcabcabcabcabcabc hello hello hello".to_vec();
+        data.extend((0..20000u32).map(|i| (i * i % 251) as u8));
+        data.extend(std::iter::repeat_n(7u8, 1000));
+        data.push(0);
+
+        let compressed = zlib_compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(zlib_decompress(&compressed).unwrap(), data);
+        assert_eq!(
+            zlib_decompress(&zlib_compress(&[])).unwrap(),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn inflates_dynamic_and_stored_blocks() {
+        // zlib level 9 output, a single dynamic Huffman block
+        const DYNAMIC: [u8; 51] = [
+            0x78, 0xda, 0xed, 0xca, 0x51, 0x06, 0x00, 0x40, 0x08, 0x05, 0xc0, 0xb3, 0x46, 0x8f,
+            0x22, 0xc5, 0xab, 0xee, 0xbf, 0xe7, 0x58, 0x9a, 0xef, 0x81, 0xb9, 0x27, 0x45, 0xbb,
+            0x2d, 0x7c, 0x49, 0x45, 0x4f, 0xc4, 0x0a, 0x0b, 0x18, 0x8f, 0x14, 0xa9, 0xc6, 0x9d,
+            0x3b, 0x77, 0xbe, 0x3e, 0x0f, 0x6a, 0x3d, 0xa4, 0xa5,
+        ];
+        let letters = b"etaoinshrdlu";
+        let expected: Vec<u8> = (0..1000usize)
+            .map(|i| letters[(i * i * 31 + i / 3) % 12])
+            .collect();
+        assert_eq!(zlib_decompress(&DYNAMIC).unwrap(), expected);
+
+        // level 0: one stored block
+        let mut stored = vec![0x78, 0x01, 0x01, 5, 0, !5, !0];
+        stored.extend(b"hello");
+        stored.extend(adler32(b"hello").to_be_bytes());
+        assert_eq!(zlib_decompress(&stored).unwrap(), b"hello");
+
+        let mut corrupt = DYNAMIC;
+        corrupt[50] ^= 1;
+        assert!(zlib_decompress(&corrupt).is_err());
+        assert!(zlib_decompress(&DYNAMIC[..30]).is_err());
+    }
+
+    #[test]
+    fn adler32_matches_reference() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+}
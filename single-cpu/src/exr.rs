@@ -0,0 +1,195 @@
+//! Single-part, scanline, uncompressed OpenEXR writer with 32-bit float
+//! R, G and B channels.
+
+use std::io::{self, Write};
+
+use crate::film::Film;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+/// version 2, no flags (single-part scanline file)
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+// channels must be stored in alphabetical order
+const CHANNELS: [&str; 3] = ["B", "G", "R"];
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+fn box2i(width: u32, height: u32) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect()
+}
+
+pub fn write_exr(film: &Film, out: &mut dyn Write) -> io::Result<()> {
+    let mut channels = vec![];
+    for name in CHANNELS {
+        channels.extend(name.as_bytes());
+        channels.push(0);
+        channels.extend(PIXEL_TYPE_FLOAT.to_le_bytes());
+        channels.extend([0, 0, 0, 0]); // pLinear + reserved
+        channels.extend(1i32.to_le_bytes()); // x sampling
+        channels.extend(1i32.to_le_bytes()); // y sampling
+    }
+    channels.push(0);
+
+    let mut header = vec![];
+    header.extend(MAGIC);
+    header.extend(VERSION);
+    write_attribute(&mut header, "channels", "chlist", &channels);
+    write_attribute(&mut header, "compression", "compression", &[0]); // NO_COMPRESSION
+    write_attribute(
+        &mut header,
+        "dataWindow",
+        "box2i",
+        &box2i(film.width, film.height),
+    );
+    write_attribute(
+        &mut header,
+        "displayWindow",
+        "box2i",
+        &box2i(film.width, film.height),
+    );
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // INCREASING_Y
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // uncompressed files hold one scanline per chunk:
+    // y (i32), data size (i32), then each channel's row of floats
+    let data_size = film.width as usize * CHANNELS.len() * 4;
+    let chunk_size = 8 + data_size;
+    let table_size = film.height as usize * 8;
+    let first_chunk = header.len() + table_size;
+
+    out.write_all(&header)?;
+    for y in 0..film.height as usize {
+        out.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for y in 0..film.height {
+        chunk.clear();
+        chunk.extend((y as i32).to_le_bytes());
+        chunk.extend((data_size as i32).to_le_bytes());
+        let row: Vec<_> = (0..film.width).map(|x| film.pixel(x, y)).collect();
+        for channel in CHANNELS {
+            for c in &row {
+                let v = match channel {
+                    "R" => c.x,
+                    "G" => c.y,
+                    _ => c.z,
+                };
+                chunk.extend(v.to_le_bytes());
+            }
+        }
+        out.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn read_i32(bytes: &[u8], pos: usize) -> i32 {
+        i32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    fn read_cstr(bytes: &[u8], pos: &mut usize) -> String {
+        let end = *pos + bytes[*pos..].iter().position(|&b| b == 0).unwrap();
+        let s = String::from_utf8(bytes[*pos..end].to_vec()).unwrap();
+        *pos = end + 1;
+        s
+    }
+
+    #[test]
+    fn exr_round_trips() {
+        let mut film = Film::new(7, 3);
+        for y in 0..3 {
+            for x in 0..7 {
+                film.add_sample(x, y, Color::new(x as f32 * 100.0, y as f32, -0.5));
+            }
+        }
+        let mut bytes = vec![];
+        write_exr(&film, &mut bytes).unwrap();
+        assert_eq!(bytes[..4], MAGIC);
+
+        // parse the header attributes
+        let mut pos = 8;
+        let mut names = vec![];
+        loop {
+            let name = read_cstr(&bytes, &mut pos);
+            if name.is_empty() {
+                break;
+            }
+            let kind = read_cstr(&bytes, &mut pos);
+            let size = read_i32(&bytes, pos) as usize;
+            pos += 4;
+            if name == "dataWindow" {
+                assert_eq!(kind, "box2i");
+                let window: Vec<_> = (0..4).map(|i| read_i32(&bytes, pos + i * 4)).collect();
+                assert_eq!(window, [0, 0, 6, 2]);
+            }
+            names.push(name);
+            pos += size;
+        }
+        for required in [
+            "channels",
+            "compression",
+            "dataWindow",
+            "displayWindow",
+            "lineOrder",
+            "pixelAspectRatio",
+            "screenWindowCenter",
+            "screenWindowWidth",
+        ] {
+            assert!(names.iter().any(|n| n == required), "missing {required}");
+        }
+
+        for y in 0..3u32 {
+            let offset = u64::from_le_bytes(
+                bytes[pos + y as usize * 8..pos + y as usize * 8 + 8]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            assert_eq!(read_i32(&bytes, offset), y as i32);
+            assert_eq!(read_i32(&bytes, offset + 4), 7 * 3 * 4);
+            let value = |channel: usize, x: usize| {
+                let p = offset + 8 + (channel * 7 + x) * 4;
+                f32::from_le_bytes(bytes[p..p + 4].try_into().unwrap())
+            };
+            for x in 0..7 {
+                let c = film.pixel(x, y);
+                // B, G, R
+                assert_eq!(
+                    [
+                        value(2, x as usize),
+                        value(1, x as usize),
+                        value(0, x as usize)
+                    ],
+                    [c.x, c.y, c.z]
+                );
+            }
+        }
+    }
+}
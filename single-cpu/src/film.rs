@@ -0,0 +1,308 @@
+use crate::{color::Color, filter::Filter};
+
+/// dark pixels measure their error against this luminance instead of their
+/// mean, noise hardly shows there
+const DARK_LUMINANCE: f32 = 0.05;
+
+/// Framebuffer accumulating linear radiance per pixel
+///
+/// Each pixel keeps the weighted sum of its samples, the final value is the
+/// weighted mean; samples are splatted into their neighbours by a
+/// reconstruction `Filter`. No clamping or gamma is applied here, that is
+/// left to the image writers. The luminance of the samples also goes into a
+/// running mean and variance, which adaptive sampling uses to tell when a
+/// pixel has converged.
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    sum: Vec<Color>,
+    weight: Vec<f32>,
+    stats: Vec<PixelStats>,
+}
+
+/// running mean and variance of the sample luminance (Welford)
+#[derive(Clone, Copy, Default)]
+struct PixelStats {
+    count: u32,
+    mean: f32,
+    /// sum of squared differences from the mean
+    m2: f32,
+}
+
+impl PixelStats {
+    fn add(&mut self, x: f32) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// both sets of samples together (Chan et al.)
+    fn combine(&mut self, other: &PixelStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let share = other.count as f32 / count as f32;
+        self.m2 += other.m2 + delta * delta * self.count as f32 * share;
+        self.mean += delta * share;
+        self.count = count;
+    }
+
+    /// unbiased sample variance, 0 until there are two samples
+    fn variance(&self) -> f32 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f32
+        }
+    }
+}
+
+fn luminance(c: Color) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            sum: vec![Color::zero(); len],
+            weight: vec![0.0; len],
+            stats: vec![PixelStats::default(); len],
+        }
+    }
+
+    #[inline]
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, radiance: Color) {
+        let index = self.index(x, y);
+        self.sum[index] += radiance;
+        self.weight[index] += 1.0;
+        self.stats[index].add(luminance(radiance));
+    }
+
+    /// a sample `offset` from the center of pixel (x, y), weighted by
+    /// `filter` into every pixel it reaches. Only pixel (x, y) counts it
+    /// for the variance
+    pub fn add_filtered_sample(
+        &mut self,
+        x: u32,
+        y: u32,
+        offset: [f32; 2],
+        radiance: Color,
+        filter: &Filter,
+    ) {
+        let reach = filter.reach() as i64;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (px, py) = (x as i64 + dx, y as i64 + dy);
+                if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                    continue;
+                }
+                let weight = filter.evaluate(dx as f32 - offset[0], dy as f32 - offset[1]);
+                if weight != 0.0 {
+                    let index = self.index(px as u32, py as u32);
+                    self.sum[index] += weight * radiance;
+                    self.weight[index] += weight;
+                }
+            }
+        }
+        let index = self.index(x, y);
+        self.stats[index].add(luminance(radiance));
+    }
+
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.stats[self.index(x, y)].count
+    }
+
+    /// standard error of the mean luminance relative to the mean, infinite
+    /// with fewer than two samples
+    pub fn relative_error(&self, x: u32, y: u32) -> f32 {
+        let stats = &self.stats[self.index(x, y)];
+        if stats.count < 2 {
+            return f32::INFINITY;
+        }
+        let standard_error = (stats.variance() / stats.count as f32).sqrt();
+        standard_error / stats.mean.max(DARK_LUMINANCE)
+    }
+
+    /// 全画素の平均サンプル数
+    pub fn mean_sample_count(&self) -> f32 {
+        let total: u64 = self.stats.iter().map(|s| s.count as u64).sum();
+        total as f32 / self.stats.len().max(1) as f32
+    }
+
+    /// samples per pixel as colours, from black (none) over blue, red and
+    /// yellow to white (the most any pixel got)
+    pub fn sample_heatmap(&self) -> Film {
+        let ramp = [
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        ];
+        let max = self.stats.iter().map(|s| s.count).max().unwrap_or(0).max(1);
+        let mut heatmap = Film::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = self.sample_count(x, y) as f32 / max as f32 * 4.0;
+                let i = (t as usize).min(3);
+                let f = t - i as f32;
+                heatmap.add_sample(x, y, ramp[i] * (1.0 - f) + ramp[i + 1] * f);
+            }
+        }
+        heatmap
+    }
+
+    /// 平均の radiance。サンプルが無い画素は黒
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let index = self.index(x, y);
+        if self.weight[index] > 0.0 {
+            self.sum[index] / self.weight[index]
+        } else {
+            Color::zero()
+        }
+    }
+
+    /// 上から下、左から右の順に全画素
+    pub fn pixels(&self) -> impl Iterator<Item = Color> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| self.pixel(x, y)))
+    }
+
+    /// tile を (x0, y0) の位置に足し込む
+    pub fn merge(&mut self, tile: &Film, x0: u32, y0: u32) {
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let src = tile.index(x, y);
+                let dst = self.index(x0 + x, y0 + y);
+                self.sum[dst] += tile.sum[src];
+                self.weight[dst] += tile.weight[src];
+                self.stats[dst].combine(&tile.stats[src]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn averages_samples_and_merges_tiles() {
+        let mut tile = Film::new(2, 2);
+        tile.add_sample(1, 0, Color::new(1.0, 2.0, 4.0));
+        tile.add_sample(1, 0, Color::new(3.0, 0.0, 4.0));
+
+        let mut film = Film::new(4, 3);
+        film.merge(&tile, 2, 1);
+
+        let p = film.pixel(3, 1);
+        assert_eq!((p.x, p.y, p.z), (2.0, 1.0, 4.0));
+        // radiance above 1.0 is kept
+        assert_eq!(film.pixels().filter(|c| c.z == 4.0).count(), 1);
+        assert_eq!(film.pixel(0, 0).length(), 0.0);
+    }
+
+    #[test]
+    fn tracks_the_variance_across_merged_tiles() {
+        let values = [0.2, 1.5, 0.7, 0.0, 3.1, 0.4, 0.9];
+        let mut whole = Film::new(1, 1);
+        let (mut first, mut second) = (Film::new(1, 1), Film::new(1, 1));
+        for (i, &v) in values.iter().enumerate() {
+            let grey = Color::new(v, v, v);
+            whole.add_sample(0, 0, grey);
+            if i < 3 { &mut first } else { &mut second }.add_sample(0, 0, grey);
+        }
+        let mut merged = Film::new(1, 1);
+        merged.merge(&first, 0, 0);
+        merged.merge(&second, 0, 0);
+
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (n - 1.0);
+        let expected = (variance / n).sqrt() / mean;
+        for film in [&whole, &merged] {
+            assert_eq!(film.sample_count(0, 0), 7);
+            assert!((film.relative_error(0, 0) - expected).abs() < 1e-5);
+        }
+
+        // a single sample tells nothing about the error
+        let mut single = Film::new(1, 1);
+        single.add_sample(0, 0, Color::new(1.0, 1.0, 1.0));
+        assert_eq!(single.relative_error(0, 0), f32::INFINITY);
+    }
+
+    #[test]
+    fn filtered_samples_keep_a_flat_image_flat() {
+        let grey = Color::new(0.5, 0.5, 0.5);
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let filter = Filter::new(FilterKind::from_name(name).unwrap());
+            let mut film = Film::new(5, 4);
+            for y in 0..4 {
+                for x in 0..5 {
+                    for i in 0..16 {
+                        let offset = [(i % 4) as f32 / 4.0 - 0.375, (i / 4) as f32 / 4.0 - 0.375];
+                        film.add_filtered_sample(x, y, offset, grey, &filter);
+                    }
+                }
+            }
+            for p in film.pixels() {
+                assert!((p - grey).length() < 1e-4, "{name}: {}", p.x);
+            }
+            assert_eq!(film.sample_count(2, 2), 16);
+        }
+
+        // at the smallest radius every pixel still weighs its own samples,
+        // even those close to its edges
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let kind = FilterKind::from_name(name).unwrap();
+            let filter = Filter {
+                kind,
+                radius: kind.min_radius(),
+            };
+            let mut film = Film::new(3, 3);
+            for y in 0..3 {
+                for x in 0..3 {
+                    for i in 0..4 {
+                        let offset = [(i % 2) as f32 * 0.98 - 0.49, (i / 2) as f32 * 0.5 - 0.49];
+                        film.add_filtered_sample(x, y, offset, grey, &filter);
+                    }
+                }
+            }
+            for p in film.pixels() {
+                assert!((p - grey).length() < 1e-4, "{name}: {}", p.x);
+            }
+        }
+
+        // a wide filter spreads a lone sample over its neighbours
+        let mut film = Film::new(3, 3);
+        film.add_filtered_sample(1, 1, [-0.25, 0.0], grey, &Filter::new(FilterKind::Tent));
+        assert!((film.pixel(0, 1) - grey).length() < 1e-6);
+        assert_eq!(film.sample_count(0, 1), 0);
+    }
+
+    #[test]
+    fn heatmap_runs_from_black_to_white() {
+        let mut film = Film::new(3, 1);
+        for (x, count) in [(1, 2), (2, 8)] {
+            for _ in 0..count {
+                film.add_sample(x, 0, Color::zero());
+            }
+        }
+        let heatmap = film.sample_heatmap();
+        let p = |x| heatmap.pixel(x, 0);
+        assert_eq!(p(0).length(), 0.0);
+        assert!((p(1) - Color::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        assert!((p(2) - Color::new(1.0, 1.0, 1.0)).length() < 1e-6);
+        assert!((film.mean_sample_count() - 10.0 / 3.0).abs() < 1e-6);
+    }
+}
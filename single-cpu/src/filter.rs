@@ -0,0 +1,231 @@
+//! Pixel reconstruction filters
+//!
+//! Every camera sample is splatted into the pixels whose centers lie within
+//! the filter radius, weighted by the filter at the offset between the two.
+//! A pixel is the weighted mean of everything it received, so wider filters
+//! trade a little sharpness for less aliasing on high-contrast edges.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    /// constant over the radius
+    Box,
+    /// linear fall off to zero at the radius
+    Tent,
+    /// Gaussian with sigma = radius / 3, shifted down to reach zero at the
+    /// radius
+    Gaussian,
+    /// Mitchell–Netravali cubic with B = C = 1/3, slightly negative lobes
+    Mitchell,
+    /// sinc windowed by a sinc stretched over the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const NAMES: &str = "box, tent, gaussian, mitchell, lanczos";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "box" => Some(FilterKind::Box),
+            "tent" | "triangle" => Some(FilterKind::Tent),
+            "gaussian" => Some(FilterKind::Gaussian),
+            "mitchell" | "mitchell-netravali" => Some(FilterKind::Mitchell),
+            "lanczos" => Some(FilterKind::Lanczos),
+            _ => None,
+        }
+    }
+
+    /// the name `from_name` takes
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    /// radius in pixels used unless one is given
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+
+    /// smallest radius that still gives every sample a positive weight in
+    /// its own pixel, below it pixels can end up black. Mitchell turns
+    /// negative past half its radius
+    pub fn min_radius(self) -> f32 {
+        match self {
+            FilterKind::Mitchell => 1.0,
+            _ => 0.5,
+        }
+    }
+}
+
+/// Separable filter over offsets in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    /// weight of a sample `(dx, dy)` pixels away from a pixel center, may be
+    /// negative
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let (x, r) = (x.abs(), self.radius);
+        if x > r {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x,
+            FilterKind::Gaussian => {
+                let gaussian = |x: f32| (-x * x * 4.5 / (r * r)).exp();
+                gaussian(x) - gaussian(r)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+
+    /// how many pixels away from its own a sample can land, for offsets
+    /// within [-0.5, 0.5)
+    pub fn reach(&self) -> u32 {
+        ((self.radius + 0.5).ceil() - 1.0).max(0.0) as u32
+    }
+}
+
+impl Default for Filter {
+    /// one sample, one pixel
+    fn default() -> Self {
+        Self::new(FilterKind::Box)
+    }
+}
+
+/// the cubic over [0, 2]
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else {
+        (-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    };
+    value / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    let px = std::f32::consts::PI * x;
+    px.sin() / px
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    #[test]
+    fn filters_peak_at_the_center_and_end_at_the_radius() {
+        for kind in KINDS {
+            let filter = Filter::new(kind);
+            let r = filter.radius;
+            let center = filter.evaluate(0.0, 0.0);
+            assert!(center > 0.0, "{kind:?}");
+            for i in 1..=100 {
+                let x = r * i as f32 / 100.0;
+                assert!(filter.evaluate(x, 0.0) <= center, "{kind:?} at {x}");
+                // separable and symmetric
+                let (a, b) = (filter.evaluate(x, 0.3), filter.evaluate(-0.3, -x));
+                assert!((a - b).abs() < 1e-6, "{kind:?} at {x}");
+            }
+            assert_eq!(filter.evaluate(r * 1.01, 0.0), 0.0, "{kind:?}");
+            if kind != FilterKind::Box {
+                assert!(filter.evaluate(r, 0.0).abs() < 1e-5, "{kind:?}");
+            }
+        }
+
+        // the cubic is continuous where its pieces meet, and 1D it
+        // integrates to 1 with the negative lobes included
+        let mitchell_filter = Filter::new(FilterKind::Mitchell);
+        assert!((mitchell(1.0 - 1e-4) - mitchell(1.0)).abs() < 1e-3);
+        let n = 4000;
+        let integral: f32 = (0..n)
+            .map(|i| mitchell_filter.evaluate_1d(-2.0 + 4.0 * (i as f32 + 0.5) / n as f32))
+            .sum::<f32>()
+            * 4.0
+            / n as f32;
+        assert!((integral - 1.0).abs() < 1e-3, "{integral}");
+        assert!(mitchell_filter.evaluate(1.5, 0.0) < 0.0);
+
+        // sinc is zero on every whole pixel but the center
+        let lanczos = Filter::new(FilterKind::Lanczos);
+        for x in [1.0, 2.0] {
+            assert!(lanczos.evaluate(x, 0.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn reach_covers_the_radius() {
+        let reach = |kind, radius| Filter { kind, radius }.reach();
+        assert_eq!(Filter::default().reach(), 0);
+        assert_eq!(reach(FilterKind::Tent, 1.0), 1);
+        assert_eq!(reach(FilterKind::Gaussian, 1.5), 1);
+        assert_eq!(reach(FilterKind::Mitchell, 2.0), 2);
+        assert_eq!(reach(FilterKind::Lanczos, 3.0), 3);
+        assert_eq!(
+            FilterKind::from_name("Mitchell"),
+            Some(FilterKind::Mitchell)
+        );
+        assert_eq!(FilterKind::from_name("sinc"), None);
+        for kind in KINDS {
+            assert_eq!(FilterKind::from_name(kind.name()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn min_radius_keeps_the_own_pixel_positive() {
+        for kind in KINDS {
+            let filter = Filter {
+                kind,
+                radius: kind.min_radius(),
+            };
+            for i in 0..100 {
+                let x = -0.5 + (i as f32 + 0.5) / 100.0;
+                assert!(filter.evaluate(x, 0.0) > 0.0, "{kind:?} at {x}");
+            }
+            assert!(kind.min_radius() <= kind.default_radius());
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Golden image regression tests
+//!
+//! Small canonical scenes are rendered at a fixed seed and compared with the
+//! references in `tests/golden/`. After an intended change to the images,
+//! `UPDATE_GOLDEN=1 cargo test golden` rewrites the references.
+
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    builtin::BuiltinScene,
+    bvh::BvhNode,
+    camera::{Background, Camera},
+    color::{linear_to_srgb, Color},
+    film::Film,
+    hit_record::{HitRecord, Hittable, HittableList},
+    image::{load_image, save_image, Image, ImageFormat},
+    interval::Interval,
+    material::{Lambertian, Metal},
+    ray::Ray,
+    scene::Scene,
+    sphere::Sphere,
+    tonemap::DisplayTransform,
+    vec3::{Point3, Vec3},
+};
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+const SEED: u64 = 2024;
+/// BLOCK x BLOCK pixels are averaged before comparing, which keeps the
+/// sampling noise out of the metric but not a misplaced edge
+const BLOCK: u32 = 8;
+/// largest RMS difference of the block averages, sRGB encoded in [0, 1]
+const TOLERANCE: f32 = 0.02;
+
+fn render(camera: &mut Camera, world: &dyn Hittable, lights: &HittableList, width: u32) -> Film {
+    camera.image_width = width;
+    camera.seed = SEED;
+    camera.threads = 0;
+    camera.render(world, lights)
+}
+
+fn render_builtin(scene: BuiltinScene, width: u32, spp: u32) -> Film {
+    let Scene {
+        mut camera,
+        world,
+        lights,
+    } = scene.build();
+    camera.samples_per_pixel = spp as f32;
+    render(&mut camera, &BvhNode::new(world), &lights, width)
+}
+
+/// what a display shows: clamped and sRGB encoded
+fn encoded(linear: Color) -> Color {
+    let c = DisplayTransform::default().apply(linear);
+    Color::new(
+        linear_to_srgb(c.x),
+        linear_to_srgb(c.y),
+        linear_to_srgb(c.z),
+    )
+}
+
+/// RMS difference of the BLOCK x BLOCK averages
+fn block_difference(film: &Film, reference: &Image) -> f32 {
+    assert_eq!(
+        (film.width, film.height),
+        (reference.width, reference.height),
+        "image size changed"
+    );
+    let (blocks_x, blocks_y) = (film.width.div_ceil(BLOCK), film.height.div_ceil(BLOCK));
+    let mut squared = 0.0;
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let mut difference = Color::zero();
+            let mut count = 0.0;
+            for y in by * BLOCK..((by + 1) * BLOCK).min(film.height) {
+                for x in bx * BLOCK..((bx + 1) * BLOCK).min(film.width) {
+                    difference += encoded(film.pixel(x, y)) - encoded(reference.pixel(x, y));
+                    count += 1.0;
+                }
+            }
+            squared += (difference / count).length_squared() / 3.0;
+        }
+    }
+    (squared / (blocks_x * blocks_y) as f32).sqrt()
+}
+
+fn check(name: &str, film: &Film) {
+    let path = PathBuf::from(GOLDEN_DIR).join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        save_image(
+            film,
+            &path,
+            ImageFormat::Png16,
+            &DisplayTransform::default(),
+        )
+        .unwrap();
+        return;
+    }
+    let reference = load_image(&path).unwrap_or_else(|err| {
+        panic!(
+            "cannot read {}: {err}, run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    let difference = block_difference(film, &reference);
+    assert!(
+        difference <= TOLERANCE,
+        "{name} differs from {} by {difference} (tolerance {TOLERANCE}). If the change is \
+         intended, run with UPDATE_GOLDEN=1 and review the new image",
+        path.display()
+    );
+}
+
+/// spheres overlapping in depth, listed from the farthest to the nearest
+fn overlapping_spheres() -> Vec<Box<dyn Hittable>> {
+    let sphere = |x: f32, z: f32, radius: f32, color: Color| -> Box<dyn Hittable> {
+        Box::new(Sphere::new(
+            Point3::new(x, radius - 0.5, z),
+            radius,
+            Arc::new(Lambertian::new(color)),
+        ))
+    };
+    vec![
+        Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            Arc::new(Metal::new(Color::new(0.6, 0.6, 0.6), 0.4)),
+        )),
+        sphere(0.6, -2.5, 0.8, Color::new(0.1, 0.2, 0.7)),
+        sphere(-0.3, -1.6, 0.5, Color::new(0.7, 0.2, 0.1)),
+        sphere(0.2, -0.8, 0.25, Color::new(0.1, 0.6, 0.2)),
+    ]
+}
+
+fn occlusion_camera() -> Camera {
+    let mut camera = Camera::new(
+        4.0 / 3.0,
+        48,
+        50.0,
+        64.0,
+        8,
+        Point3::new(0.0, 0.3, 1.0),
+        Point3::new(0.0, 0.0, -1.5),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    camera.background = Background::Sky;
+    camera
+}
+
+fn list(objects: Vec<Box<dyn Hittable>>) -> HittableList {
+    let mut world = HittableList::new();
+    for object in objects {
+        world.add(object);
+    }
+    world
+}
+
+fn render_occlusion(world: &dyn Hittable) -> Film {
+    render(&mut occlusion_camera(), world, &HittableList::new(), 48)
+}
+
+#[test]
+fn occlusion_golden() {
+    check("occlusion", &render_occlusion(&list(overlapping_spheres())));
+}
+
+#[test]
+fn three_spheres_golden() {
+    check(
+        "three_spheres",
+        &render_builtin(BuiltinScene::ThreeSpheres, 64, 64),
+    );
+}
+
+#[test]
+fn cornell_golden() {
+    check("cornell", &render_builtin(BuiltinScene::CornellBox, 40, 64));
+}
+
+#[test]
+fn cornell_smoke_golden() {
+    check(
+        "cornell_smoke",
+        &render_builtin(BuiltinScene::CornellSmoke, 40, 64),
+    );
+}
+
+#[test]
+fn nearest_hit_does_not_depend_on_object_order() {
+    // a ray through all three spheres sees the nearest one
+    let world = list(overlapping_spheres());
+    let ray = Ray::new(Point3::new(0.2, -0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+    let hit = world
+        .hit(&ray, Interval::new(0.001, f32::INFINITY))
+        .unwrap();
+    assert!((hit.p.z - -0.55).abs() < 1e-4, "{:?}", hit.p);
+
+    let forward = render_occlusion(&world);
+    let mut reversed = overlapping_spheres();
+    reversed.reverse();
+    let reversed = render_occlusion(&list(reversed));
+    for (a, b) in forward.pixels().zip(reversed.pixels()) {
+        assert!((a - b).length() == 0.0, "{a:?} vs {b:?}");
+    }
+}
+
+/// the first object that reports a hit wins, as `HittableList` used to do
+struct FirstHit(Vec<Box<dyn Hittable>>);
+
+impl Hittable for FirstHit {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.0.iter().find_map(|object| object.hit(r, interval))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.0.iter().fold(Aabb::empty(), |bbox, object| {
+            Aabb::enclosing(bbox, object.bounding_box())
+        })
+    }
+}
+
+#[test]
+fn golden_metric_catches_broken_occlusion() {
+    let film = render_occlusion(&list(overlapping_spheres()));
+    let reference = Image::new(film.width, film.height, film.pixels().collect());
+    let broken = render_occlusion(&FirstHit(overlapping_spheres()));
+    let difference = block_difference(&broken, &reference);
+    assert!(difference > 3.0 * TOLERANCE, "{difference}");
+
+    // another seed only changes the noise
+    let mut camera = occlusion_camera();
+    camera.seed = SEED + 1;
+    camera.threads = 0;
+    let other_seed = camera.render(&list(overlapping_spheres()), &HittableList::new());
+    let noise = block_difference(&other_seed, &reference);
+    assert!(noise < 0.5 * TOLERANCE, "{noise}");
+}
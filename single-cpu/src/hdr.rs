@@ -0,0 +1,263 @@
+//! Floating point image formats that keep the linear radiance unclamped:
+//! Radiance RGBE (`.hdr`) and Portable Float Map (`.pfm`).
+
+use std::io::{self, Write};
+
+use crate::{color::Color, film::Film};
+
+/// largest value RGBE can store: mantissa 255/256 at exponent 127
+const RGBE_MAX: f32 = 255.0 / 256.0 * 1.7014118e38;
+
+/// shared-exponent encoding, 8 bit mantissa per channel. Infinite and
+/// too large values are clamped to `RGBE_MAX`, NaN is stored as 0
+pub fn to_rgbe(color: Color) -> [u8; 4] {
+    let clean = |x: f32| if x.is_nan() { 0.0 } else { x.min(RGBE_MAX) };
+    let color = Color::new(clean(color.x), clean(color.y), clean(color.z));
+    let max = color.x.max(color.y).max(color.z);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // max = m * 2^exponent with m in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2f32.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / 2f32.powi(exponent);
+    let byte = |x: f32| (x.max(0.0) * scale).min(255.0) as u8;
+    [
+        byte(color.x),
+        byte(color.y),
+        byte(color.z),
+        (exponent + 128) as u8,
+    ]
+}
+
+/// 1 チャンネル分を run-length 符号化する。
+/// 4 つ以上続く値は (128 + 長さ, 値)、それ以外は (長さ, 値...)
+fn write_rle_channel(out: &mut Vec<u8>, data: &[u8]) {
+    const MIN_RUN: usize = 4;
+    let mut pos = 0;
+    while pos < data.len() {
+        // find the next run long enough to be worth encoding
+        let mut run_start = pos;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = data[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|&&v| v == data[run_start])
+                .count();
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+            run_length = 0;
+        }
+
+        // literals before the run
+        while pos < run_start {
+            let count = (run_start - pos).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[pos..pos + count]);
+            pos += count;
+        }
+        if run_length >= MIN_RUN {
+            out.push(128 + run_length as u8);
+            out.push(data[run_start]);
+            pos = run_start + run_length;
+        }
+    }
+}
+
+/// Radiance RGBE with new-style run-length encoded scanlines
+pub fn write_radiance_hdr(film: &Film, out: &mut dyn Write) -> io::Result<()> {
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n")?;
+    writeln!(out, "-Y {} +X {}", film.height, film.width)?;
+
+    let width = film.width as usize;
+    let rle = (8..=0x7fff).contains(&width);
+    let mut scanline = Vec::with_capacity(width * 4);
+    let mut channel = Vec::with_capacity(width);
+    for y in 0..film.height {
+        let pixels: Vec<[u8; 4]> = (0..film.width).map(|x| to_rgbe(film.pixel(x, y))).collect();
+        scanline.clear();
+        if rle {
+            scanline.extend([2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for c in 0..4 {
+                channel.clear();
+                channel.extend(pixels.iter().map(|p| p[c]));
+                write_rle_channel(&mut scanline, &channel);
+            }
+        } else {
+            // too narrow or too wide for RLE, write flat pixels
+            scanline.extend(pixels.iter().flatten());
+        }
+        out.write_all(&scanline)?;
+    }
+    Ok(())
+}
+
+/// Portable Float Map: little-endian RGB floats, bottom row first
+pub fn write_pfm(film: &Film, out: &mut dyn Write) -> io::Result<()> {
+    // a negative scale marks little-endian data
+    write!(out, "PF\n{} {}\n-1.0\n", film.width, film.height)?;
+
+    let mut row = Vec::with_capacity(film.width as usize * 12);
+    for y in (0..film.height).rev() {
+        row.clear();
+        for x in 0..film.width {
+            let c = film.pixel(x, y);
+            for v in [c.x, c.y, c.z] {
+                row.extend(v.to_le_bytes());
+            }
+        }
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_rgbe(rgbe: [u8; 4]) -> Color {
+        if rgbe[3] == 0 {
+            return Color::zero();
+        }
+        let scale = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+        Color::new(
+            (rgbe[0] as f32 + 0.5) * scale,
+            (rgbe[1] as f32 + 0.5) * scale,
+            (rgbe[2] as f32 + 0.5) * scale,
+        )
+    }
+
+    fn hdr_film() -> Film {
+        let mut film = Film::new(23, 5);
+        for y in 0..film.height {
+            for x in 0..film.width {
+                // flat areas for runs, and values far above 1.0
+                let c = if x < 10 {
+                    Color::new(0.25, 0.5, 1.0)
+                } else {
+                    Color::new(x as f32 * 13.7, y as f32 * 0.01, 1000.0 / (x + 1) as f32)
+                };
+                film.add_sample(x, y, c);
+            }
+        }
+        film
+    }
+
+    /// header の後ろの画素データ部分
+    fn skip_header(bytes: &[u8], lines: usize) -> &[u8] {
+        let mut pos = 0;
+        for _ in 0..lines {
+            pos += bytes[pos..].iter().position(|&b| b == b'\n').unwrap() + 1;
+        }
+        &bytes[pos..]
+    }
+
+    fn decode_rle_scanline(data: &[u8], width: usize) -> (Vec<[u8; 4]>, usize) {
+        assert_eq!(&data[..4], &[2, 2, (width >> 8) as u8, width as u8]);
+        let mut pos = 4;
+        let mut channels = [vec![], vec![], vec![], vec![]];
+        for channel in &mut channels {
+            while channel.len() < width {
+                let count = data[pos] as usize;
+                pos += 1;
+                if count > 128 {
+                    channel.extend(std::iter::repeat_n(data[pos], count - 128));
+                    pos += 1;
+                } else {
+                    channel.extend_from_slice(&data[pos..pos + count]);
+                    pos += count;
+                }
+            }
+        }
+        let pixels = (0..width)
+            .map(|x| {
+                [
+                    channels[0][x],
+                    channels[1][x],
+                    channels[2][x],
+                    channels[3][x],
+                ]
+            })
+            .collect();
+        (pixels, pos)
+    }
+
+    #[test]
+    fn rgbe_keeps_radiance_above_one() {
+        for c in [
+            Color::new(1.0, 0.5, 0.25),
+            Color::new(1234.5, 2.0, 0.001),
+            Color::new(0.003, 0.002, 0.001),
+        ] {
+            let d = from_rgbe(to_rgbe(c));
+            let max = c.x.max(c.y).max(c.z);
+            for (a, b) in [(c.x, d.x), (c.y, d.y), (c.z, d.z)] {
+                assert!((a - b).abs() <= max / 128.0, "{c:?} -> {d:?}");
+            }
+        }
+        assert_eq!(to_rgbe(Color::zero()), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgbe_clamps_infinite_and_huge_values() {
+        for huge in [f32::INFINITY, f32::MAX, 3e38] {
+            assert_eq!(to_rgbe(Color::new(huge, huge, huge)), [255, 255, 255, 255]);
+        }
+        // the other channels keep their share of the clamped maximum
+        let d = from_rgbe(to_rgbe(Color::new(f32::INFINITY, 1e38, 1.0)));
+        assert!((d.x - RGBE_MAX).abs() <= RGBE_MAX / 128.0);
+        assert!((d.y - 1e38).abs() <= RGBE_MAX / 128.0);
+
+        // NaN carries no radiance
+        assert_eq!(
+            to_rgbe(Color::new(f32::NAN, f32::NAN, f32::NAN)),
+            [0, 0, 0, 0]
+        );
+        let d = from_rgbe(to_rgbe(Color::new(f32::NAN, 2.0, 0.5)));
+        assert!(d.x < 2.0 / 128.0, "{}", d.x);
+        assert!((d.y - 2.0).abs() < 2.0 / 128.0);
+    }
+
+    #[test]
+    fn radiance_hdr_round_trips() {
+        let film = hdr_film();
+        let mut bytes = vec![];
+        write_radiance_hdr(&film, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"#?RADIANCE\n"));
+
+        let mut data = skip_header(&bytes, 4);
+        for y in 0..film.height {
+            let (pixels, used) = decode_rle_scanline(data, film.width as usize);
+            for x in 0..film.width {
+                assert_eq!(pixels[x as usize], to_rgbe(film.pixel(x, y)));
+            }
+            data = &data[used..];
+        }
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn pfm_round_trips() {
+        let film = hdr_film();
+        let mut bytes = vec![];
+        write_pfm(&film, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"PF\n23 5\n-1.0\n"));
+
+        let data = skip_header(&bytes, 3);
+        assert_eq!(data.len(), 23 * 5 * 12);
+        let value = |i: usize| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        for y in 0..film.height {
+            for x in 0..film.width {
+                // first row in the file is the bottom of the image
+                let i = (((film.height - 1 - y) * film.width + x) * 3) as usize;
+                let c = film.pixel(x, y);
+                assert_eq!([value(i), value(i + 1), value(i + 2)], [c.x, c.y, c.z]);
+            }
+        }
+    }
+}
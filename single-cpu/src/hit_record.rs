@@ -1,14 +1,28 @@
 use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     interval::Interval,
     material::Material,
     ray::Ray,
+    sampler::Sampler,
     vec3::{Point3, Vec3},
 };
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Aabb;
+
+    /// solid angle density of `random(origin)` producing `direction`.
+    /// 0 for objects that can't be sampled as lights
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f32 {
+        0.0
+    }
+
+    /// random direction from `origin` towards the object
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub struct HitRecord {
@@ -16,6 +30,9 @@ pub struct HitRecord {
     pub normal_vec: Vec3,
     pub material: Option<Arc<dyn Material>>,
     pub t: f32,
+    /// surface coordinates
+    pub u: f32,
+    pub v: f32,
     pub front_face: bool,
 }
 impl HitRecord {
@@ -25,6 +42,8 @@ impl HitRecord {
             normal_vec,
             material: None,
             t,
+            u: 0.0,
+            v: 0.0,
             front_face,
         }
     }
@@ -39,19 +58,56 @@ impl HitRecord {
             -outward_normal_vec
         };
     }
+
+    /// 補間した法線で shading 用の法線を置き換える。
+    /// front_face は幾何法線で決めたものを保ったまま、同じ側に向ける
+    pub fn set_shading_normal_vec(&mut self, shading_normal_vec: Vec3) {
+        self.normal_vec = if shading_normal_vec.dot(self.normal_vec) < 0.0 {
+            -shading_normal_vec
+        } else {
+            shading_normal_vec
+        };
+    }
+}
+
+/// shared objects, e.g. a model placed once as is and again through an
+/// `Instance`
+impl Hittable for Arc<dyn Hittable> {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        self.as_ref().hit(r, interval)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.as_ref().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.as_ref().random(origin, sampler)
+    }
 }
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 impl HittableList {
     pub fn new() -> Self {
-        Self { objects: vec![] }
+        Self {
+            objects: vec![],
+            bbox: Aabb::empty(),
+        }
     }
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.bbox = Aabb::empty();
     }
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.bbox = Aabb::enclosing(self.bbox, object.bounding_box());
         self.objects.push(object);
     }
 }
@@ -59,15 +115,39 @@ impl HittableList {
 impl Hittable for HittableList {
     fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
         let mut closest_so_far = interval.max;
+        let mut closest = None;
 
         for object in &self.objects {
             if let Some(rec) = object.hit(r, Interval::new(interval.min, closest_so_far)) {
                 closest_so_far = rec.t;
-                return Some(rec);
+                closest = Some(rec);
             }
         }
 
-        None
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    /// 各 object を同じ確率で選ぶ混合分布
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f32
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let count = self.objects.len();
+        let index = ((sampler.get_1d() * count as f32) as usize).min(count - 1);
+        self.objects[index].random(origin, sampler)
     }
 }
 
@@ -0,0 +1,272 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{srgb_to_linear, write_color, Color},
+    exr::write_exr,
+    film::Film,
+    hdr::{write_pfm, write_radiance_hdr},
+    png::{read_png, write_png, BitDepth},
+    tonemap::DisplayTransform,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Png16,
+    /// Radiance RGBE
+    Hdr,
+    Pfm,
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "png16" => Some(ImageFormat::Png16),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+}
+
+/// Decoded image in linear RGB, rows from top to bottom
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+}
+
+/// PNG か PPM (P3/P6) を読み込む。形式は先頭の bytes で判断する
+pub fn load_image(path: &Path) -> io::Result<Image> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(b"\x89PNG") {
+        read_png(&bytes)
+    } else if bytes.starts_with(b"P3") || bytes.starts_with(b"P6") {
+        read_ppm(&bytes)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown image format, expected PNG or PPM",
+        ))
+    }
+}
+
+fn invalid_ppm(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PPM: {message}"))
+}
+
+/// PPM header / P3 sample tokenizer
+struct PpmTokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl PpmTokens<'_> {
+    /// 空白と `#` コメントを読み飛ばして次の token
+    fn next(&mut self) -> Option<&[u8]> {
+        loop {
+            while self.bytes.get(self.pos)?.is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.bytes[self.pos] != b'#' {
+                break;
+            }
+            while *self.bytes.get(self.pos)? != b'\n' {
+                self.pos += 1;
+            }
+        }
+        let start = self.pos;
+        while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        Some(&self.bytes[start..self.pos])
+    }
+
+    fn number(&mut self, name: &str) -> io::Result<u32> {
+        self.next()
+            .and_then(|t| std::str::from_utf8(t).ok()?.parse().ok())
+            .ok_or_else(|| invalid_ppm(&format!("invalid {name}")))
+    }
+}
+
+/// plain (P3) or binary (P6) PPM, samples are sRGB encoded
+pub fn read_ppm(bytes: &[u8]) -> io::Result<Image> {
+    let binary = bytes.starts_with(b"P6");
+    let mut tokens = PpmTokens { bytes, pos: 2 };
+    let width = tokens.number("width")?;
+    let height = tokens.number("height")?;
+    let max = tokens.number("maximum value")?;
+    if width == 0 || height == 0 || !(1..=65535).contains(&max) {
+        return Err(invalid_ppm("invalid header"));
+    }
+    // a malformed header must not overflow the sample count
+    let count = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(|| invalid_ppm("image too large"))?;
+
+    let samples: Vec<u32> = if binary {
+        // a single whitespace byte separates the header from the data
+        let data = &bytes[(tokens.pos + 1).min(bytes.len())..];
+        let sample_size = if max < 256 { 1 } else { 2 };
+        if data.len() / sample_size < count {
+            return Err(invalid_ppm("not enough pixel data"));
+        }
+        if max < 256 {
+            data.iter().take(count).map(|&v| v as u32).collect()
+        } else {
+            data.chunks_exact(2)
+                .take(count)
+                .map(|v| u16::from_be_bytes([v[0], v[1]]) as u32)
+                .collect()
+        }
+    } else {
+        (0..count)
+            .map_while(|_| tokens.number("sample").ok())
+            .collect()
+    };
+    if samples.len() < count {
+        return Err(invalid_ppm("not enough pixel data"));
+    }
+
+    let level = |v: u32| srgb_to_linear(v.min(max) as f32 / max as f32);
+    let pixels = samples
+        .chunks_exact(3)
+        .map(|rgb| Color::new(level(rgb[0]), level(rgb[1]), level(rgb[2])))
+        .collect();
+    Ok(Image::new(width, height, pixels))
+}
+
+/// film を path に書き出す。path が `-` なら stdout
+pub fn save_image(
+    film: &Film,
+    path: &Path,
+    format: ImageFormat,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    if path == Path::new("-") {
+        let mut out = io::stdout().lock();
+        return write_image(film, &mut out, format, display);
+    }
+
+    let file = File::create(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("cannot create {}: {err}", path.display()),
+        )
+    })?;
+    write_image(film, &mut BufWriter::new(file), format, display)
+}
+
+/// `display` is only used by the 8/16-bit formats, the floating point
+/// formats store the linear radiance as is
+pub fn write_image(
+    film: &Film,
+    out: &mut dyn Write,
+    format: ImageFormat,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    match format {
+        ImageFormat::Ppm => write_ppm(film, out, display)?,
+        ImageFormat::Png => write_png(film, out, BitDepth::Eight, display)?,
+        ImageFormat::Png16 => write_png(film, out, BitDepth::Sixteen, display)?,
+        ImageFormat::Hdr => write_radiance_hdr(film, out)?,
+        ImageFormat::Pfm => write_pfm(film, out)?,
+        ImageFormat::Exr => write_exr(film, out)?,
+    }
+    out.flush()
+}
+
+/// plain text P3 PPM
+pub fn write_ppm(film: &Film, out: &mut dyn Write, display: &DisplayTransform) -> io::Result<()> {
+    writeln!(out, "P3")?;
+    writeln!(out, "{} {}", film.width, film.height)?;
+    writeln!(out, "255")?; // colors range from 0 to 255
+
+    for pixel_color in film.pixels() {
+        write_color(out, display.apply(pixel_color))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_plain_and_binary_ppm() {
+        let plain = b"P3\n# comment\n2 1\n255\n255 0 0  0 128 255\n";
+        let image = read_ppm(plain).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!((image.pixel(0, 0) - Color::new(1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((image.pixel(1, 0).y - srgb_to_linear(128.0 / 255.0)).abs() < 1e-6);
+
+        let mut binary = b"P6 1 2 65535\n".to_vec();
+        binary.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff]);
+        let image = read_ppm(&binary).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert!((image.pixel(0, 0) - Color::new(1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((image.pixel(0, 1) - Color::new(0.0, 0.0, 1.0)).length() < 1e-6);
+
+        assert!(read_ppm(b"P3 2 2 255 1 2 3").is_err());
+        assert!(read_ppm(b"P6 0 1 255\n").is_err());
+
+        // sizes whose sample count doesn't fit in 32 bits are rejected, not
+        // overflowed
+        for header in [
+            &b"P6 4294967295 4294967295 255\n"[..],
+            b"P3 65536 65536 255 1 2 3",
+        ] {
+            let err = read_ppm(header).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn reads_back_written_ppm() {
+        let mut film = Film::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                film.add_sample(x, y, Color::new(x as f32 / 2.0, y as f32, 0.25));
+            }
+        }
+        let mut ppm = vec![];
+        write_ppm(&film, &mut ppm, &DisplayTransform::default()).unwrap();
+        let image = read_ppm(&ppm).unwrap();
+        for (i, expected) in film.pixels().enumerate() {
+            let actual = image.pixel(i as u32 % 3, i as u32 / 3);
+            assert!(
+                (actual - expected).length() < 0.01,
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+}
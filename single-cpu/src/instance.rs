@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit_record::{HitRecord, Hittable},
+    interval::Interval,
+    ray::Ray,
+    transform::Transform,
+};
+
+/// Shared geometry placed in the world by a transform
+///
+/// Many instances can point at the same object (a mesh, a box, a whole
+/// BVH), only the transform is stored per instance.
+pub struct Instance {
+    pub object: Arc<dyn Hittable>,
+    pub transform: Transform,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let bbox = transform.bbox(object.bounding_box());
+        Self {
+            object,
+            transform,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        // direction は正規化しないので、t は object 空間でも同じ値になる
+        let object_ray = Ray::new(
+            self.transform.inverse_point(r.origin),
+            self.transform.inverse_vector(r.direction),
+        )
+        .with_time(r.time);
+        let mut record = self.object.hit(&object_ray, interval)?;
+
+        record.p = self.transform.point(record.p);
+        // an affine map keeps the side of the surface the ray is on, so
+        // front_face and the flipped normal stay valid
+        record.normal_vec = self.transform.normal(record.normal_vec);
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian,
+        quad::make_box,
+        sphere::Sphere,
+        utils::Rng,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn matches_geometry_built_in_place() {
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let unit_sphere: Arc<dyn Hittable> =
+            Arc::new(Sphere::new(Point3::zero(), 1.0, material.clone()));
+        let offset = Vec3::new(2.0, -1.0, -5.0);
+        let instance = Instance::new(
+            unit_sphere,
+            Transform::identity()
+                .scale(Vec3::new(1.5, 1.5, 1.5))
+                .translate(offset),
+        );
+        let reference = Sphere::new(offset, 1.5, material.clone());
+
+        let all = Interval::new(0.001, f32::INFINITY);
+        let mut rng = Rng::new(1);
+        for i in 0..100 {
+            let target = offset + Vec3::random_range(&mut rng, -1.4, 1.4);
+            let origin = Point3::new(i as f32 * 0.1, 0.0, 3.0);
+            let ray = Ray::new(origin, target - origin);
+            let (a, b) = (instance.hit(&ray, all), reference.hit(&ray, all));
+            assert_eq!(a.is_some(), b.is_some());
+            if let (Some(a), Some(b)) = (a, b) {
+                assert!((a.t - b.t).abs() < 1e-4);
+                assert!((a.p - b.p).length() < 1e-3);
+                assert!((a.normal_vec - b.normal_vec).length() < 1e-3);
+                assert_eq!(a.front_face, b.front_face);
+            }
+        }
+    }
+
+    #[test]
+    fn rotated_box_normals_face_outwards() {
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let cube: Arc<dyn Hittable> = Arc::new(make_box(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            material,
+        ));
+        let instance = Instance::new(
+            cube,
+            Transform::identity()
+                .scale(Vec3::new(1.0, 3.0, 1.0))
+                .rotate_y(45.0),
+        );
+
+        // the rotated box is sqrt(2) wide along x
+        let bbox = instance.bounding_box();
+        assert!((bbox.x.max - 2f32.sqrt()).abs() < 1e-3);
+        assert!((bbox.y.max - 3.0).abs() < 1e-3);
+
+        // hits the edge of the rotated cube head on, the normal points
+        // back at the ray at 45 degrees
+        let ray = Ray::new(Point3::new(5.0, 2.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = instance
+            .hit(&ray, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!((hit.t - (5.0 - 2f32.sqrt())).abs() < 1e-3);
+        assert!(hit.front_face);
+        assert!((hit.normal_vec.x - 0.5f32.sqrt()).abs() < 1e-3);
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Interval {
     pub min: f32,
     pub max: f32,
@@ -9,6 +9,14 @@ impl Interval {
         Self { min, max }
     }
 
+    /// 2つの区間を両方含む区間
+    pub fn enclosing(a: Interval, b: Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
     pub fn default() -> Self {
         Self {
             min: f32::INFINITY,
@@ -20,6 +28,7 @@ impl Interval {
         self.max - self.min
     }
 
+    #[allow(dead_code)]
     pub fn contains(&self, x: f32) -> bool {
         self.min <= x && x <= self.max
     }
@@ -37,6 +46,12 @@ impl Interval {
         }
     }
 
+    /// 両側に delta / 2 ずつ広げた区間
+    pub fn expand(&self, delta: f32) -> Self {
+        let padding = delta / 2.0;
+        Self::new(self.min - padding, self.max + padding)
+    }
+
     pub fn empty() -> Self {
         Self::default()
     }
@@ -1,85 +1,118 @@
-use std::{f32::consts::PI, sync::Arc};
+use std::process::ExitCode;
 
 use crate::{
-    camera::Camera,
-    color::Color,
-    hit_record::HittableList,
-    material::{Dielectric, Lambertian, Metal},
-    sphere::Sphere,
-    vec3::{Point3, Vec3},
+    builtin::BuiltinScene,
+    bvh::BvhNode,
+    cli::{parse_args, Command, Options, USAGE},
+    image::save_image,
+    scene::{load_scene, Scene},
+    tonemap::DisplayTransform,
 };
 
+mod aabb;
+mod builtin;
+mod bvh;
 mod camera;
+mod cli;
 mod color;
+mod deflate;
+mod exr;
+mod film;
+mod filter;
+#[cfg(test)]
+mod golden;
+mod hdr;
 mod hit_record;
+mod image;
+mod instance;
 mod interval;
 mod material;
+mod medium;
+mod obj;
+mod onb;
+mod perlin;
+mod png;
+mod quad;
 mod ray;
+mod sampler;
+mod scene;
 mod sphere;
+mod texture;
+mod tonemap;
+mod transform;
+mod triangle;
 mod utils;
 mod vec3;
 
-fn main() {
-    let aspect_ratio = 16f32 / 9f32;
-    let image_width = 400u32;
-    // let r = (PI / 4.0).cos();
+// exit codes
+const EXIT_RENDER_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
 
-    let mut camera = Camera::new(
-        aspect_ratio,
-        image_width,
-        20.0,
-        80.0,
-        30,
-        Point3::new(-2.0, 2.0, 1.0),
-        Point3::new(0.0, 0.0, -1.0),
-        Vec3::new(0.0, 1.0, 0.0),
-    );
-    camera.defocus_angle = 10.0;
-    camera.focus_distance = 3.4;
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            eprintln!("Run with --help for usage.");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
 
-    // world
-    let mut world = HittableList::new();
-
-    // let mat_left = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
-    // let mat_right = Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
-    //
-    // world.add(Box::new(Sphere::new(Vec3::new(-r, 0.0, -1.0), r, mat_left)));
-    // world.add(Box::new(Sphere::new(Vec3::new(r, 0.0, -1.0), r, mat_right)));
-
-    let mat_center = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, 0.0, -1.0),
-        0.5,
-        mat_center,
-    )));
-
-    let mat_left = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-1.0, 0.0, -1.0),
-        0.5,
-        mat_left,
-    )));
-
-    let mat_bubble = Arc::new(Dielectric::new(1.0 / 1.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-1.0, 0.0, -1.0),
-        0.4,
-        mat_bubble,
-    )));
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(EXIT_RENDER_ERROR)
+        }
+    }
+}
 
-    let mat_right = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
-    world.add(Box::new(Sphere::new(
-        Point3::new(1.0, 0.0, -1.0),
-        0.5,
-        mat_right,
-    )));
+fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let Scene {
+        mut camera,
+        world,
+        lights,
+    } = match &options.scene {
+        Some(path) => load_scene(path)?,
+        None => options
+            .builtin
+            .unwrap_or(BuiltinScene::ThreeSpheres)
+            .build(),
+    };
+    options.apply(&mut camera)?;
+    if world.objects.is_empty() {
+        return Err("scene has no objects".into());
+    }
+    let world = BvhNode::new(world);
 
-    let mat_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, -100.5, -1.0),
-        100.0,
-        mat_ground,
-    )));
-    camera.render(&world);
+    let film = camera.render(&world, &lights);
+    let output = options.output_path();
+    save_image(
+        &film,
+        output,
+        options.image_format()?,
+        &options.display_transform(),
+    )?;
+    eprintln!("Wrote {}", output.display());
+    if camera.adaptive.is_some() {
+        eprintln!(
+            "{:.1} samples per pixel on average",
+            film.mean_sample_count()
+        );
+    }
+    if let Some((path, format)) = options.heatmap()? {
+        save_image(
+            &film.sample_heatmap(),
+            path,
+            format,
+            &DisplayTransform::default(),
+        )?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
 }
 
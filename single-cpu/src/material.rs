@@ -1,27 +1,68 @@
+use std::sync::Arc;
+
 use crate::{
     color::Color,
     hit_record::HitRecord,
     ray::Ray,
-    utils::f32_random,
-    vec3::{random_unit_vector, reflect, refract, unit_vector},
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::{reflect, refract, sample_unit_vector, unit_vector, Vec3},
 };
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> (Ray, Color);
+pub trait Material: Send + Sync {
+    /// 散乱した ray と減衰。吸収されたら None
+    ///
+    /// Random choices take their numbers from `sampler`.
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)>;
+
+    /// radiance emitted from the hit point back along `ray_in`
+    fn emitted(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Color {
+        Color::zero()
+    }
+
+    /// BRDF times cos(theta) for light arriving from `direction`
+    ///
+    /// None for materials that only scatter into a few directions (mirror,
+    /// glass), light sampling can't find those directions.
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Option<Color> {
+        None
+    }
+
+    /// scatter が `direction` を選ぶ確率密度 (立体角あたり)
+    ///
+    /// `eval / pdf` is the attenuation `scatter` returns. 0 where `eval` is
+    /// None.
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f32 {
+        0.0
+    }
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub texture: Arc<dyn Texture>,
 }
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Self { albedo }
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> (Ray, Color) {
-        let scatter_direction = hit_record.normal_vec + random_unit_vector();
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let scatter_direction = hit_record.normal_vec + sample_unit_vector(sampler.get_2d());
 
         // Catch degenerate scatter direction
         let scatter_direction = if scatter_direction.near_zero() {
@@ -30,28 +71,101 @@ impl Material for Lambertian {
             scatter_direction
         };
 
-        (Ray::new(hit_record.p, scatter_direction), self.albedo)
+        let attenuation = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some((
+            Ray::new(hit_record.p, scatter_direction).with_time(ray_in.time),
+            attenuation,
+        ))
+    }
+
+    /// albedo / pi * cos
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Color> {
+        let cosine = hit_record.normal_vec.dot(unit_vector(direction)).max(0.0);
+        let albedo = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(albedo * (cosine / std::f32::consts::PI))
+    }
+
+    /// normal + a random unit vector is cosine weighted: cos / pi
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f32 {
+        hit_record.normal_vec.dot(unit_vector(direction)).max(0.0) / std::f32::consts::PI
     }
 }
 
 pub struct Metal {
-    pub albedo: Color,
+    pub texture: Arc<dyn Texture>,
     pub fuzz: f32,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f32) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn with_texture(texture: Arc<dyn Texture>, fuzz: f32) -> Self {
         Self {
-            albedo,
+            texture,
             fuzz: if fuzz < 1f32 { fuzz } else { 1.0 },
         }
     }
+
+    fn reflected(ray_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        unit_vector(reflect(
+            unit_vector(ray_in.direction),
+            hit_record.normal_vec,
+        ))
+    }
 }
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> (Ray, Color) {
-        let mut reflected = reflect(unit_vector(ray_in.direction), hit_record.normal_vec);
-        reflected = unit_vector(reflected) + (self.fuzz * random_unit_vector());
-        (Ray::new(hit_record.p, reflected), self.albedo)
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let reflected = Self::reflected(ray_in, hit_record)
+            + (self.fuzz * sample_unit_vector(sampler.get_2d()));
+        let attenuation = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some((
+            Ray::new(hit_record.p, reflected).with_time(ray_in.time),
+            attenuation,
+        ))
+    }
+
+    /// albedo * pdf, so that eval / pdf is the albedo `scatter` returns.
+    /// A sharp mirror (fuzz 0) has no lobe to sample lights with
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Color> {
+        if self.fuzz <= 0.0 {
+            return None;
+        }
+        let albedo = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(albedo * self.pdf(ray_in, hit_record, direction))
+    }
+
+    /// `scatter` picks a uniform point on the sphere of radius fuzz around
+    /// the mirror direction. The direction hits that sphere up to twice, each
+    /// crossing adds the area density converted to solid angle: t^2 / |cos|
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f32 {
+        if self.fuzz <= 0.0 {
+            return 0.0;
+        }
+        let center = Self::reflected(ray_in, hit_record);
+        let direction = unit_vector(direction);
+        let b = direction.dot(center);
+        let discriminant = b * b - (center.length_squared() - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let area_pdf = 1.0 / (4.0 * std::f32::consts::PI * self.fuzz * self.fuzz);
+        let sqrtd = discriminant.sqrt();
+        [b - sqrtd, b + sqrtd]
+            .into_iter()
+            .filter(|&t| t > 1e-6)
+            .map(|t| {
+                let normal = (t * direction - center) / self.fuzz;
+                let cosine = direction.dot(normal).abs().max(1e-6);
+                area_pdf * t * t / cosine
+            })
+            .sum()
     }
 }
 
@@ -71,7 +185,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> (Ray, Color) {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let refraction_ratio = if hit_record.front_face {
             1.0 / self.refraction_index
@@ -84,14 +203,173 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > f32_random() {
-                reflect(unit_direction, hit_record.normal_vec)
-            } else {
-                refract(unit_direction, hit_record.normal_vec, refraction_ratio)
-            };
-
-        (Ray::new(hit_record.p, direction), attenuation)
+        // drawn either way, so later dimensions don't shift
+        let u = sampler.get_1d();
+        let direction = if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > u {
+            reflect(unit_direction, hit_record.normal_vec)
+        } else {
+            refract(unit_direction, hit_record.normal_vec, refraction_ratio)
+        };
+
+        Some((
+            Ray::new(hit_record.p, direction).with_time(ray_in.time),
+            attenuation,
+        ))
+    }
+}
+
+/// Phase function of a participating medium: scatters the same in every
+/// direction
+pub struct Isotropic {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let attenuation = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some((
+            Ray::new(hit_record.p, sample_unit_vector(sampler.get_2d())).with_time(ray_in.time),
+            attenuation,
+        ))
+    }
+
+    /// 全方向に一様なので albedo / 4pi。cos はかからない
+    fn eval(&self, _ray_in: &Ray, hit_record: &HitRecord, _direction: Vec3) -> Option<Color> {
+        let albedo = self.texture.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(albedo / (4.0 * std::f32::consts::PI))
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f32 {
+        1.0 / (4.0 * std::f32::consts::PI)
+    }
+}
+
+/// Area light: emits `emit` from both sides and scatters nothing
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _hit_record: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sampler::IndependentSampler, vec3::Point3};
+
+    #[test]
+    fn scatter_pdfs_match_the_sampled_directions() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let record = HitRecord::new(Point3::zero(), normal, 1.0, true);
+        let ray_in = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let albedo = Color::new(0.8, 0.6, 0.4);
+        let materials: [(&str, Box<dyn Material>); 4] = [
+            ("lambertian", Box::new(Lambertian::new(albedo))),
+            ("metal", Box::new(Metal::new(albedo, 0.3))),
+            ("rough metal", Box::new(Metal::new(albedo, 1.0))),
+            ("isotropic", Box::new(Isotropic::new(albedo))),
+        ];
+        // directions near the mirror direction (1, 1, 0)
+        let mirror = unit_vector(Vec3::new(1.0, 1.0, 0.0));
+        let in_cone = |d: Vec3| unit_vector(d).dot(mirror) > 0.98;
+
+        let trials = 200_000;
+        for (name, material) in &materials {
+            // integrate over an equal-area grid on the sphere, the metal pdf
+            // is too spiky at the rim of its lobe for random directions
+            let mut total = 0.0;
+            let mut cone = 0.0;
+            let n = 500;
+            for i in 0..n {
+                for j in 0..n {
+                    let u = [(i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32];
+                    let d = sample_unit_vector(u);
+                    let pdf = material.pdf(&ray_in, &record, d);
+                    total += pdf;
+                    if in_cone(d) {
+                        cone += pdf;
+                    }
+                }
+            }
+            let sphere = 4.0 * std::f32::consts::PI / (n * n) as f32;
+            assert!(
+                (total * sphere - 1.0).abs() < 0.05,
+                "{name}: {}",
+                total * sphere
+            );
+
+            // the pdf predicts how often scatter lands in the cone
+            let mut sampler = IndependentSampler::new(3);
+            let hits = (0..trials)
+                .filter(|_| {
+                    in_cone(
+                        material
+                            .scatter(&ray_in, &record, &mut sampler)
+                            .unwrap()
+                            .0
+                            .direction,
+                    )
+                })
+                .count();
+            let sampled = hits as f32 / trials as f32;
+            let predicted = cone * sphere;
+            assert!(
+                (predicted - sampled).abs() < 0.02,
+                "{name}: {predicted} vs {sampled}"
+            );
+
+            let d = material
+                .scatter(&ray_in, &record, &mut sampler)
+                .unwrap()
+                .0
+                .direction;
+            let f = material.eval(&ray_in, &record, d).unwrap();
+            let pdf = material.pdf(&ray_in, &record, d);
+            if pdf > 0.0 {
+                assert!((f / pdf - albedo).length() < 1e-3, "{name}");
+            }
+        }
+
+        // mirrors and glass are left to scatter
+        let mirror = Metal::new(albedo, 0.0);
+        assert!(mirror.eval(&ray_in, &record, normal).is_none());
+        assert_eq!(mirror.pdf(&ray_in, &record, normal), 0.0);
+        assert!(Dielectric::new(1.5)
+            .eval(&ray_in, &record, normal)
+            .is_none());
     }
 }
 
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit_record::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    utils::{mix_seed, Rng},
+    vec3::Vec3,
+};
+
+/// Homogeneous fog or smoke filling a closed, convex `boundary`
+///
+/// A ray passing through travels an exponentially distributed distance
+/// (mean 1 / density) before it scatters, or leaves the volume untouched.
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Hittable>,
+    neg_inv_density: f32,
+    /// usually `Isotropic`
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f32,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        assert!(density > 0.0, "density must be positive");
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        // 境界に入る点と出る点。ray の始点が中にあっても見つかるように全区間で探す
+        let entry = self.boundary.hit(r, Interval::universe())?;
+        let exit = self
+            .boundary
+            .hit(r, Interval::new(entry.t + 0.0001, f32::INFINITY))?;
+
+        let t_min = entry.t.max(interval.min).max(0.0);
+        let t_max = exit.t.min(interval.max);
+        if t_min >= t_max {
+            return None;
+        }
+
+        let ray_length = r.direction.length();
+        let distance_inside = (t_max - t_min) * ray_length;
+        // keyed by where the ray enters, so overlapping volumes draw
+        // independent distances
+        let u = Rng::new(mix_seed(r.stream, t_min.to_bits() as u64)).f32();
+        let hit_distance = self.neg_inv_density * (1.0 - u).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_min + hit_distance / ray_length;
+        // the normal and the face are meaningless inside a volume
+        let mut record = HitRecord::new(r.at(t), Vec3::new(1.0, 0.0, 0.0), t, true);
+        record.material = Some(self.phase_function.clone());
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Isotropic, quad::make_box, sphere::Sphere, vec3::Point3};
+
+    fn fog(density: f32) -> ConstantMedium {
+        let boundary = make_box(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Arc::new(Isotropic::new(Color::zero())),
+        );
+        ConstantMedium::new(
+            Arc::new(boundary),
+            density,
+            Arc::new(Isotropic::new(Color::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn box_boundary_reports_entry_and_exit() {
+        // a list of quads has to give the far side once the near one is
+        // behind the interval
+        let boundary = make_box(
+            Point3::new(-1.0, -1.0, -1.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Arc::new(Isotropic::new(Color::zero())),
+        );
+        let ray = Ray::new(Point3::new(0.2, 0.1, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let entry = boundary.hit(&ray, Interval::universe()).unwrap();
+        let exit = boundary
+            .hit(&ray, Interval::new(entry.t + 0.0001, f32::INFINITY))
+            .unwrap();
+        assert!((entry.t - 2.0).abs() < 1e-5 && (exit.t - 3.0).abs() < 1e-5);
+
+        // from inside, the entry is behind the origin
+        let inside = Ray::new(Point3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let entry = boundary.hit(&inside, Interval::universe()).unwrap();
+        assert!((entry.t + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scatters_inside_at_the_expected_rate() {
+        let medium = fog(0.5);
+        let all = Interval::new(0.001, f32::INFINITY);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let trials = 20000;
+        let mut hits = 0;
+        for stream in 0..trials {
+            let ray = ray.with_stream(stream);
+            let hit = medium.hit(&ray, all);
+            // the same ray always scatters at the same point
+            assert_eq!(
+                hit.as_ref().map(|h| h.t),
+                medium.hit(&ray, all).map(|h| h.t)
+            );
+            if let Some(hit) = hit {
+                assert!((4.0..=6.0).contains(&hit.t), "{}", hit.t);
+                hits += 1;
+            }
+        }
+        // Beer-Lambert: 1 - exp(-density * 2) of the rays scatter
+        let expected = 1.0 - (-1.0f32).exp();
+        let rate = hits as f32 / trials as f32;
+        assert!((rate - expected).abs() < 0.02, "{rate} vs {expected}");
+
+        // starting inside the fog, scattering happens ahead of the origin
+        let inside = Ray::new(Point3::new(0.0, 0.0, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        for stream in 0..100 {
+            if let Some(hit) = fog(100.0).hit(&inside.with_stream(stream), all) {
+                assert!(hit.t > 0.0 && hit.t < 1.5);
+            }
+        }
+
+        // missing the boundary misses the fog
+        let beside = Ray::new(Point3::new(3.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(medium.hit(&beside, all).is_none());
+        let sphere = ConstantMedium::new(
+            Arc::new(Sphere::new(
+                Point3::zero(),
+                1.0,
+                Arc::new(Isotropic::new(Color::zero())),
+            )),
+            1000.0,
+            Arc::new(Isotropic::new(Color::zero())),
+        );
+        assert!(sphere.hit(&ray, Interval::new(0.001, 3.9)).is_none());
+        assert!(sphere.hit(&ray, all).is_some());
+    }
+}
@@ -0,0 +1,565 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    image::load_image,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    texture::{ImageTexture, SolidColor, Texture},
+    triangle::{MeshFace, TriangleMesh},
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Material description read from an MTL file
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// diffuse colour (Kd)
+    pub diffuse: Color,
+    /// specular colour (Ks)
+    pub specular: Color,
+    /// specular exponent (Ns)
+    pub shininess: f32,
+    /// index of refraction (Ni)
+    pub refraction_index: f32,
+    /// opacity (d), 1.0 is opaque
+    pub dissolve: f32,
+    /// emitted radiance (Ke)
+    pub emission: Color,
+    /// diffuse texture path (map_Kd), relative to the MTL file
+    pub diffuse_map: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::zero(),
+            shininess: 0.0,
+            refraction_index: 1.0,
+            dissolve: 1.0,
+            emission: Color::zero(),
+            diffuse_map: None,
+        }
+    }
+
+    /// 近い既存の material に変換する
+    ///
+    /// - Ke があれば DiffuseLight
+    /// - 透明 (d < 1) なら Dielectric (Ni)
+    /// - Ks が Kd より強ければ Metal (Ns が大きいほど fuzz が小さい)
+    /// - それ以外は Lambertian (Kd)
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        self.to_material_with(Arc::new(SolidColor::new(self.diffuse)))
+    }
+
+    /// `to_material` with `diffuse` in place of Kd, for a loaded map_Kd
+    pub fn to_material_with(&self, diffuse: Arc<dyn Texture>) -> Arc<dyn Material> {
+        let luminance = |c: Color| 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+
+        if luminance(self.emission) > 0.0 {
+            Arc::new(DiffuseLight::new(self.emission))
+        } else if self.dissolve < 1.0 {
+            let refraction_index = if self.refraction_index > 1.0 {
+                self.refraction_index
+            } else {
+                1.5
+            };
+            Arc::new(Dielectric::new(refraction_index))
+        } else if luminance(self.specular) > luminance(self.diffuse) {
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else {
+            Arc::new(Lambertian::with_texture(diffuse))
+        }
+    }
+}
+
+/// A named run of faces (`g` / `o`)
+pub struct ObjGroup {
+    pub name: String,
+    pub faces: Range<usize>,
+}
+
+pub struct ObjModel {
+    pub mesh: TriangleMesh,
+    pub groups: Vec<ObjGroup>,
+    /// MTL descriptions, in the same order as `mesh.materials`
+    pub materials: Vec<MtlMaterial>,
+}
+
+/// OBJ ファイルと、そこから参照される MTL ファイルを読み込む
+pub fn load_obj(path: &Path) -> Result<ObjModel, ObjError> {
+    let source = read_file(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut model = parse_obj(&source, path, |name| {
+        let mtl_path = base_dir.join(name);
+        let source = read_file(&mtl_path)?;
+        parse_mtl(&source, &mtl_path)
+    })?;
+
+    // map_Kd の画像を読み込んで diffuse を差し替える。同じ画像は共有する
+    let mut textures: HashMap<PathBuf, Arc<dyn Texture>> = HashMap::new();
+    for (material, slot) in model.materials.iter().zip(&mut model.mesh.materials) {
+        let Some(map) = &material.diffuse_map else {
+            continue;
+        };
+        let texture = match textures.get(map) {
+            Some(texture) => texture.clone(),
+            None => {
+                let image = load_image(map).map_err(|source| ObjError::Io {
+                    path: map.clone(),
+                    source,
+                })?;
+                let texture: Arc<dyn Texture> = Arc::new(ImageTexture::new(image));
+                textures.insert(map.clone(), texture.clone());
+                texture
+            }
+        };
+        *slot = material.to_material_with(texture);
+    }
+    Ok(model)
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// OBJ を解析する。`mtllib` は `load_mtl` で解決する
+pub fn parse_obj(
+    source: &str,
+    path: &Path,
+    mut load_mtl: impl FnMut(&str) -> Result<Vec<MtlMaterial>, ObjError>,
+) -> Result<ObjModel, ObjError> {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut faces = vec![];
+
+    let mut library: HashMap<String, MtlMaterial> = HashMap::new();
+    // index 0 is the default material for faces without `usemtl`
+    let mut materials = vec![MtlMaterial::new("default")];
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    let mut groups: Vec<ObjGroup> = vec![];
+    let mut group_name = String::from("default");
+    let mut group_start = 0;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let mut line = LineParser::new(raw_line, path, index + 1);
+        let Some(keyword) = line.next_token() else {
+            continue;
+        };
+
+        match keyword {
+            "v" => positions.push(line.vec3()?),
+            "vn" => normals.push(line.vec3()?),
+            "vt" => {
+                let u = line.f32("u")?;
+                // v and w are optional
+                let v = line.optional_f32("v")?.unwrap_or(0.0);
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut corners = vec![];
+                while let Some(token) = line.next_token() {
+                    corners.push(line.face_corner(
+                        token,
+                        positions.len(),
+                        uvs.len(),
+                        normals.len(),
+                    )?);
+                }
+                if corners.len() < 3 {
+                    return Err(line.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+                let has_uvs = corners.iter().all(|c| c.1.is_some());
+                let has_normals = corners.iter().all(|c| c.2.is_some());
+
+                // fan triangulation
+                for k in 1..corners.len() - 1 {
+                    let tri = [corners[0], corners[k], corners[k + 1]];
+                    faces.push(MeshFace {
+                        positions: tri.map(|c| c.0),
+                        uvs: has_uvs.then(|| tri.map(|c| c.1.unwrap())),
+                        normals: has_normals.then(|| tri.map(|c| c.2.unwrap())),
+                        material: current_material,
+                    });
+                }
+            }
+            "g" | "o" => {
+                if faces.len() > group_start {
+                    groups.push(ObjGroup {
+                        name: group_name,
+                        faces: group_start..faces.len(),
+                    });
+                }
+                group_name = line.rest().unwrap_or("default").to_string();
+                group_start = faces.len();
+            }
+            "mtllib" => {
+                let Some(names) = line.rest() else {
+                    return Err(line.error("mtllib needs a file name".to_string()));
+                };
+                for name in names.split_whitespace() {
+                    for material in load_mtl(name)? {
+                        library.insert(material.name.clone(), material);
+                    }
+                }
+            }
+            "usemtl" => {
+                let Some(name) = line.rest() else {
+                    return Err(line.error("usemtl needs a material name".to_string()));
+                };
+                current_material = match material_indices.get(name) {
+                    Some(&index) => index,
+                    None => {
+                        let Some(material) = library.get(name) else {
+                            return Err(line.error(format!("unknown material `{name}`")));
+                        };
+                        materials.push(material.clone());
+                        material_indices.insert(name.to_string(), materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+            }
+            // smoothing groups and other statements are not needed here
+            _ => {}
+        }
+    }
+
+    if faces.len() > group_start {
+        groups.push(ObjGroup {
+            name: group_name,
+            faces: group_start..faces.len(),
+        });
+    }
+
+    let mesh = TriangleMesh::new(
+        positions,
+        normals,
+        uvs,
+        faces,
+        materials.iter().map(MtlMaterial::to_material).collect(),
+    );
+    Ok(ObjModel {
+        mesh,
+        groups,
+        materials,
+    })
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut materials: Vec<MtlMaterial> = vec![];
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let mut line = LineParser::new(raw_line, path, index + 1);
+        let Some(keyword) = line.next_token() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let Some(name) = line.rest() else {
+                return Err(line.error("newmtl needs a material name".to_string()));
+            };
+            materials.push(MtlMaterial::new(name));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(line.error(format!("`{keyword}` before any newmtl")));
+        };
+        match keyword {
+            "Kd" => material.diffuse = line.vec3()?,
+            "Ks" => material.specular = line.vec3()?,
+            "Ke" => material.emission = line.vec3()?,
+            "Ns" => material.shininess = line.f32("Ns")?,
+            "Ni" => material.refraction_index = line.f32("Ni")?,
+            "d" => material.dissolve = line.f32("d")?,
+            "Tr" => material.dissolve = 1.0 - line.f32("Tr")?,
+            "map_Kd" => {
+                // options such as `-s` are not supported, the last token is the file
+                let Some(file) = line.rest().and_then(|r| r.split_whitespace().last()) else {
+                    return Err(line.error("map_Kd needs a file name".to_string()));
+                };
+                let base_dir = path.parent().unwrap_or(Path::new(""));
+                material.diffuse_map = Some(base_dir.join(file));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+struct LineParser<'a> {
+    line: &'a str,
+    tokens: SplitWhitespace<'a>,
+    path: &'a Path,
+    number: usize,
+}
+
+impl<'a> LineParser<'a> {
+    fn new(line: &'a str, path: &'a Path, number: usize) -> Self {
+        let line = line.split('#').next().unwrap_or("").trim();
+        Self {
+            line,
+            tokens: line.split_whitespace(),
+            path,
+            number,
+        }
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.number,
+            message,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    /// keyword を除いた残り
+    fn rest(&self) -> Option<&'a str> {
+        let rest = self
+            .line
+            .split_once(char::is_whitespace)
+            .map(|(_, rest)| rest.trim())?;
+        (!rest.is_empty()).then_some(rest)
+    }
+
+    fn optional_f32(&mut self, what: &str) -> Result<Option<f32>, ObjError> {
+        match self.next_token() {
+            None => Ok(None),
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(format!("invalid {what} value `{token}`"))),
+        }
+    }
+
+    fn f32(&mut self, what: &str) -> Result<f32, ObjError> {
+        self.optional_f32(what)?
+            .ok_or_else(|| self.error(format!("missing {what} value")))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Point3::new(self.f32("x")?, self.f32("y")?, self.f32("z")?))
+    }
+
+    /// `v`, `v/vt`, `v//vn`, `v/vt/vn` を 0 始まりの index にする
+    fn face_corner(
+        &self,
+        token: &str,
+        position_count: usize,
+        uv_count: usize,
+        normal_count: usize,
+    ) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+        let mut parts = token.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|s| !s.is_empty());
+        let normal = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(self.error(format!("invalid face vertex `{token}`")));
+        }
+
+        let position = self.resolve_index(position, position_count, "vertex")?;
+        let uv = uv
+            .map(|s| self.resolve_index(s, uv_count, "texture coordinate"))
+            .transpose()?;
+        let normal = normal
+            .map(|s| self.resolve_index(s, normal_count, "normal"))
+            .transpose()?;
+        Ok((position, uv, normal))
+    }
+
+    /// 1 始まり、負の値は末尾からの相対位置
+    fn resolve_index(&self, token: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {what} index `{token}`")))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "{what} index {index} out of range ({count} defined)"
+            )));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hit_record::Hittable, interval::Interval, ray::Ray};
+
+    const CUBE: &str = "\
+mtllib cube.mtl
+v -1 -1 -1
+v  1 -1 -1
+v  1  1 -1
+v -1  1 -1
+v -1 -1  1
+v  1 -1  1
+v  1  1  1
+v -1  1  1
+vn 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+g front
+usemtl red
+f 5/1/1 6/2/1 7/3/1 8/4/1
+g rest
+usemtl glass
+f 1 4 3 2
+f -8 -7 -3 -4 # negative indices
+f 2 3 7 6
+f 3 4 8 7
+f 4 1 5 8
+";
+
+    const MTL: &str = "\
+newmtl red
+Kd 0.8 0.1 0.1
+map_Kd textures/red.ppm
+newmtl glass
+Ni 1.45
+d 0.2
+";
+
+    fn parse(source: &str) -> Result<ObjModel, ObjError> {
+        parse_obj(source, Path::new("cube.obj"), |name| {
+            assert_eq!(name, "cube.mtl");
+            parse_mtl(MTL, Path::new("models/cube.mtl"))
+        })
+    }
+
+    #[test]
+    fn parses_cube_with_groups_and_materials() {
+        let model = parse(CUBE).unwrap();
+        assert_eq!(model.mesh.positions.len(), 8);
+        assert_eq!(model.mesh.faces.len(), 12);
+
+        assert_eq!(model.groups.len(), 2);
+        assert_eq!(model.groups[0].name, "front");
+        assert_eq!(model.groups[0].faces, 0..2);
+        assert_eq!(model.groups[1].name, "rest");
+        assert_eq!(model.groups[1].faces, 2..12);
+
+        let front = model.mesh.faces[0];
+        assert_eq!(front.positions, [4, 5, 6]);
+        assert_eq!(front.uvs, Some([0, 1, 2]));
+        assert_eq!(front.normals, Some([0, 0, 0]));
+        assert_eq!(model.materials[front.material].name, "red");
+        assert_eq!(
+            model.materials[front.material].diffuse_map,
+            Some(PathBuf::from("models/textures/red.ppm"))
+        );
+
+        let back = model.mesh.faces[4];
+        assert_eq!(back.positions, [0, 1, 5]);
+        assert!(back.normals.is_none());
+        let glass = &model.materials[back.material];
+        assert_eq!(glass.name, "glass");
+        assert_eq!(glass.refraction_index, 1.45);
+        assert_eq!(glass.dissolve, 0.2);
+    }
+
+    #[test]
+    fn loaded_mesh_is_hittable() {
+        let list = parse(CUBE).unwrap().mesh.into_list();
+        let hit = list
+            .hit(
+                &Ray::new(Point3::new(0.2, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0)),
+                Interval::new(0.001, f32::INFINITY),
+            )
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n";
+        match parse(source) {
+            Err(ObjError::Parse { line, message, .. }) => {
+                assert_eq!(line, 4);
+                assert!(message.contains("out of range"), "{message}");
+            }
+            _ => panic!("expected parse error"),
+        }
+
+        match parse("v 0 zero 0\n") {
+            Err(err @ ObjError::Parse { line: 1, .. }) => {
+                assert_eq!(err.to_string(), "cube.obj:1: invalid y value `zero`");
+            }
+            _ => panic!("expected parse error"),
+        }
+
+        assert!(matches!(
+            parse("v 0 0 0\nusemtl missing\n"),
+            Err(ObjError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_mtl("Kd 1 1 1\n", Path::new("a.mtl")),
+            Err(ObjError::Parse { line: 1, .. })
+        ));
+    }
+}
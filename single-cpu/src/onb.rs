@@ -0,0 +1,28 @@
+use crate::vec3::{unit_vector, Vec3};
+
+/// Orthonormal basis whose w axis is a given direction
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Self {
+        let w = unit_vector(w);
+        // w とほぼ平行にならない軸を選んで外積をとる
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(w.cross(a));
+        let u = w.cross(v);
+        Self { u, v, w }
+    }
+
+    /// basis coordinates -> world
+    pub fn transform(&self, v: Vec3) -> Vec3 {
+        v.x * self.u + v.y * self.v + v.z * self.w
+    }
+}
@@ -0,0 +1,125 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise on the integer lattice (Ken Perlin)
+///
+/// The gradient vectors and permutations come from their own `StdRng`
+/// seeded with `seed`, so the same seed always gives the same pattern.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let p = Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                );
+                let lensq = p.length_squared();
+                if lensq <= 1.0 && 1e-12 < lensq {
+                    break unit_vector(p);
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(&mut rng);
+            perm
+        };
+        Self {
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+            gradients,
+        }
+    }
+
+    /// 値はおよそ [-1, 1]。格子点では 0
+    pub fn noise(&self, p: Point3) -> f32 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        // Hermite cubic, the gradient of the noise stays continuous
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+        let wrap = |n: i64, d: i64| ((n + d) & (POINT_COUNT as i64 - 1)) as usize;
+
+        // 周りの 8 つの格子点の勾配を三線形補間する
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i, di)]
+                        ^ self.perm_y[wrap(j, dj)]
+                        ^ self.perm_z[wrap(k, dk)]];
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// fBm: `depth` octaves, each twice the frequency and half the weight
+    /// of the previous one. Always >= 0
+    pub fn turbulence(&self, p: Point3, depth: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Point3> {
+        (0..500).map(|i| {
+            let t = i as f32;
+            Point3::new(t * 0.37 - 90.0, (t * 0.11).sin() * 20.0, t * 0.053)
+        })
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        let (a, b, other) = (Perlin::new(7), Perlin::new(7), Perlin::new(8));
+        assert!(points().all(|p| a.noise(p) == b.noise(p)));
+        assert!(points().any(|p| a.noise(p) != other.noise(p)));
+    }
+
+    #[test]
+    fn noise_is_bounded_smooth_and_zero_on_the_lattice() {
+        let perlin = Perlin::new(1);
+        for p in points() {
+            let n = perlin.noise(p);
+            assert!((-1.0..=1.0).contains(&n), "noise({p:?}) = {n}");
+            // no jumps, also across lattice cells
+            let step = Vec3::new(1e-3, -1e-3, 1e-3);
+            assert!((perlin.noise(p + step) - n).abs() < 0.01);
+        }
+        assert_eq!(perlin.noise(Point3::new(3.0, -2.0, 17.0)), 0.0);
+
+        let turbulence = perlin.turbulence(Point3::new(0.3, 0.2, 0.1), 7);
+        assert!((0.0..2.0).contains(&turbulence));
+    }
+}
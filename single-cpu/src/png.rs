@@ -0,0 +1,532 @@
+use std::io::{self, Write};
+
+use crate::{
+    color::{srgb_to_linear, to_rgb16, to_rgb8, Color},
+    deflate::{zlib_compress, zlib_decompress},
+    film::Film,
+    image::Image,
+    tonemap::DisplayTransform,
+};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut c = 0xffffffffu32;
+    for &byte in bytes {
+        c = CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    c ^ 0xffffffff
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut body = Vec::with_capacity(4 + data.len());
+    body.extend_from_slice(kind);
+    body.extend_from_slice(data);
+    out.write_all(&body)?;
+    out.write_all(&crc32(&body).to_be_bytes())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// 各行で None/Sub/Up/Average/Paeth を試し、差分の絶対値和が最小のものを使う
+fn filter_scanlines(raw: &[u8], stride: usize, bpp: usize) -> Vec<u8> {
+    let rows = raw.len() / stride;
+    let mut out = Vec::with_capacity(rows * (stride + 1));
+    let zero_row = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+
+    for y in 0..rows {
+        let row = &raw[y * stride..(y + 1) * stride];
+        let prior = if y > 0 {
+            &raw[(y - 1) * stride..y * stride]
+        } else {
+            &zero_row
+        };
+
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5u8 {
+            for x in 0..stride {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let b = prior[x];
+                let c = if x >= bpp { prior[x - bpp] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[x] = row[x].wrapping_sub(predicted);
+            }
+            let score = candidate
+                .iter()
+                .map(|&v| (v as i8).unsigned_abs() as u64)
+                .sum();
+            if score < best_score {
+                best_score = score;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        out.push(best_filter);
+        out.extend_from_slice(&best);
+    }
+    out
+}
+
+/// filter_scanlines の逆。各行の先頭に filter の種類が入っている
+fn unfilter_scanlines(filtered: &[u8], stride: usize, bpp: usize) -> io::Result<Vec<u8>> {
+    let rows = filtered.len() / (stride + 1);
+    let mut raw = vec![0u8; rows * stride];
+    for y in 0..rows {
+        let filter = filtered[y * (stride + 1)];
+        let line = &filtered[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, rest) = raw.split_at_mut(y * stride);
+        let prior = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let row = &mut rest[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { row[x - bpp] } else { 0 };
+            let b = prior.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp {
+                prior.get(x - bpp).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid_png(&format!("unknown filter type {filter}"))),
+            };
+            row[x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok(raw)
+}
+
+fn invalid_png(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PNG: {message}"))
+}
+
+/// Decodes a non-interlaced PNG of any colour type and bit depth. Samples
+/// are treated as sRGB and converted to linear, alpha is dropped.
+pub fn read_png(bytes: &[u8]) -> io::Result<Image> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(invalid_png("missing signature"));
+    }
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut palette: Vec<Color> = vec![];
+    let mut idat = vec![];
+    loop {
+        let chunk = bytes
+            .get(pos..pos + 8)
+            .ok_or_else(|| invalid_png("truncated chunk"))?;
+        let len = u32::from_be_bytes(chunk[..4].try_into().unwrap()) as usize;
+        let body = bytes
+            .get(pos + 4..pos + 8 + len)
+            .ok_or_else(|| invalid_png("truncated chunk"))?;
+        let crc = bytes
+            .get(pos + 8 + len..pos + 12 + len)
+            .ok_or_else(|| invalid_png("truncated chunk"))?;
+        if u32::from_be_bytes(crc.try_into().unwrap()) != crc32(body) {
+            return Err(invalid_png("chunk checksum mismatch"));
+        }
+        let data = &body[4..];
+        match &body[..4] {
+            b"IHDR" if data.len() == 13 => header = Some(data.to_vec()),
+            b"PLTE" => {
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| {
+                        let c = |v: u8| srgb_to_linear(v as f32 / 255.0);
+                        Color::new(c(rgb[0]), c(rgb[1]), c(rgb[2]))
+                    })
+                    .collect()
+            }
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+
+    let header = header.ok_or_else(|| invalid_png("missing IHDR"))?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let (bit_depth, color_type) = (header[8] as usize, header[9]);
+    if header[12] != 0 {
+        return Err(invalid_png("interlaced images are not supported"));
+    }
+    let channels = match (color_type, bit_depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (2, 8 | 16) => 3,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (6, 8 | 16) => 4,
+        _ => {
+            return Err(invalid_png(&format!(
+                "unsupported colour type {color_type} with bit depth {bit_depth}"
+            )))
+        }
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid_png("empty image"));
+    }
+
+    let bits_per_pixel = channels * bit_depth;
+    let stride = (width as usize * bits_per_pixel).div_ceil(8);
+    let filtered = zlib_decompress(&idat)?;
+    if filtered.len() < height as usize * (stride + 1) {
+        return Err(invalid_png("not enough image data"));
+    }
+    let raw = unfilter_scanlines(
+        &filtered[..height as usize * (stride + 1)],
+        stride,
+        bits_per_pixel.div_ceil(8),
+    )?;
+
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    let sample = |row: &[u8], index: usize| -> u32 {
+        match bit_depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32,
+            8 => row[index] as u32,
+            // packed from the most significant bit
+            _ => {
+                let bit = index * bit_depth;
+                (row[bit / 8] >> (8 - bit_depth - bit % 8)) as u32 & max as u32
+            }
+        }
+    };
+    let level = |v: u32| srgb_to_linear(v as f32 / max);
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for row in raw.chunks_exact(stride) {
+        for x in 0..width as usize {
+            let s = |c: usize| sample(row, x * channels + c);
+            pixels.push(match color_type {
+                0 | 4 => Color::new(level(s(0)), level(s(0)), level(s(0))),
+                3 => *palette
+                    .get(s(0) as usize)
+                    .ok_or_else(|| invalid_png("palette index out of range"))?,
+                _ => Color::new(level(s(0)), level(s(1)), level(s(2))),
+            });
+        }
+    }
+    Ok(Image::new(width, height, pixels))
+}
+
+/// RGB PNG (sRGB encoded, 8 or 16 bit per channel)
+pub fn write_png(
+    film: &Film,
+    out: &mut dyn Write,
+    bit_depth: BitDepth,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    let bpp = match bit_depth {
+        BitDepth::Eight => 3,
+        BitDepth::Sixteen => 6,
+    };
+    let stride = film.width as usize * bpp;
+
+    let mut raw = Vec::with_capacity(stride * film.height as usize);
+    for pixel_color in film.pixels().map(|c| display.apply(c)) {
+        match bit_depth {
+            BitDepth::Eight => raw.extend(to_rgb8(pixel_color)),
+            BitDepth::Sixteen => {
+                raw.extend(to_rgb16(pixel_color).iter().flat_map(|c| c.to_be_bytes()))
+            }
+        }
+    }
+
+    let mut header = vec![];
+    header.extend(film.width.to_be_bytes());
+    header.extend(film.height.to_be_bytes());
+    header.push(bpp as u8 / 3 * 8); // bit depth
+    header.push(2); // color type: RGB
+    header.push(0); // compression: deflate
+    header.push(0); // filter method: adaptive
+    header.push(0); // no interlace
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    // rendering intent: perceptual
+    write_chunk(out, b"sRGB", &[0])?;
+    // fallback for decoders without sRGB support, 1/2.2 * 100000
+    write_chunk(out, b"gAMA", &45455u32.to_be_bytes())?;
+    write_chunk(
+        out,
+        b"IDAT",
+        &zlib_compress(&filter_scanlines(&raw, stride, bpp)),
+    )?;
+    write_chunk(out, b"IEND", &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Decoded {
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        data: Vec<u8>,
+    }
+
+    fn decode(png: &[u8]) -> Decoded {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut pos = 8;
+        let mut idat = vec![];
+        let (mut width, mut height, mut bit_depth) = (0, 0, 0);
+        loop {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let body = &png[pos + 4..pos + 8 + len];
+            let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(body));
+            let data = &body[4..];
+            match &body[..4] {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+                    bit_depth = data[8];
+                    assert_eq!(data[9], 2);
+                }
+                b"IDAT" => idat.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+            pos += 12 + len;
+        }
+
+        let bpp = bit_depth as usize / 8 * 3;
+        let stride = width as usize * bpp;
+        let data = unfilter_scanlines(&zlib_decompress(&idat).unwrap(), stride, bpp).unwrap();
+        Decoded {
+            width,
+            height,
+            bit_depth,
+            data,
+        }
+    }
+
+    fn gradient_film() -> Film {
+        let mut film = Film::new(37, 21);
+        for y in 0..film.height {
+            for x in 0..film.width {
+                film.add_sample(
+                    x,
+                    y,
+                    Color::new(x as f32 / 36.0, y as f32 / 20.0, ((x * y) % 7) as f32 / 3.0),
+                );
+            }
+        }
+        film
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn round_trips_8_bit() {
+        let film = gradient_film();
+        let mut png = vec![];
+        write_png(
+            &film,
+            &mut png,
+            BitDepth::Eight,
+            &DisplayTransform::default(),
+        )
+        .unwrap();
+
+        let decoded = decode(&png);
+        assert_eq!(
+            (decoded.width, decoded.height, decoded.bit_depth),
+            (37, 21, 8)
+        );
+        let expected: Vec<u8> = film.pixels().flat_map(to_rgb8).collect();
+        assert_eq!(decoded.data, expected);
+    }
+
+    #[test]
+    fn round_trips_16_bit() {
+        let film = gradient_film();
+        let mut png = vec![];
+        write_png(
+            &film,
+            &mut png,
+            BitDepth::Sixteen,
+            &DisplayTransform::default(),
+        )
+        .unwrap();
+
+        let decoded = decode(&png);
+        assert_eq!(
+            (decoded.width, decoded.height, decoded.bit_depth),
+            (37, 21, 16)
+        );
+        let expected: Vec<u8> = film
+            .pixels()
+            .flat_map(to_rgb16)
+            .flat_map(|c| c.to_be_bytes())
+            .collect();
+        assert_eq!(decoded.data, expected);
+    }
+
+    /// filter 0 の scanline をそのまま包んだ PNG
+    fn handmade_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        palette: &[u8],
+        scanlines: &[&[u8]],
+    ) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        let filtered: Vec<u8> = scanlines
+            .iter()
+            .flat_map(|row| [&[0u8][..], row].concat())
+            .collect();
+
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header).unwrap();
+        if !palette.is_empty() {
+            write_chunk(&mut png, b"PLTE", palette).unwrap();
+        }
+        write_chunk(&mut png, b"IDAT", &zlib_compress(&filtered)).unwrap();
+        write_chunk(&mut png, b"IEND", &[]).unwrap();
+        png
+    }
+
+    #[test]
+    fn reads_back_written_images() {
+        let film = gradient_film();
+        for bit_depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            let mut png = vec![];
+            write_png(&film, &mut png, bit_depth, &DisplayTransform::default()).unwrap();
+            let image = read_png(&png).unwrap();
+            assert_eq!((image.width, image.height), (37, 21));
+            for (i, expected) in film.pixels().enumerate() {
+                let actual = image.pixel(i as u32 % 37, i as u32 / 37);
+                for c in 0..3 {
+                    let expected = expected[c].clamp(0.0, 1.0);
+                    assert!((actual[c] - expected).abs() < 0.01, "{i}: {actual:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_packed_palette_and_alpha_images() {
+        // 2-bit grey, 4 pixels packed into one byte: 0, 1, 2, 3
+        let grey = read_png(&handmade_png(
+            5,
+            1,
+            2,
+            0,
+            &[],
+            &[&[0b00_01_10_11, 0b11_000000]],
+        ))
+        .unwrap();
+        let levels: Vec<f32> = (0..5).map(|x| grey.pixel(x, 0).x).collect();
+        assert_eq!(levels[0], 0.0);
+        assert!((levels[1] - srgb_to_linear(1.0 / 3.0)).abs() < 1e-6);
+        assert_eq!(levels[3], 1.0);
+        assert_eq!(levels[4], 1.0);
+
+        // 1-bit palette
+        let palette = [255, 0, 0, 0, 0, 255];
+        let indexed = read_png(&handmade_png(
+            3,
+            2,
+            1,
+            3,
+            &palette,
+            &[&[0b010_00000], &[0b101_00000]],
+        ))
+        .unwrap();
+        assert!((indexed.pixel(0, 0) - Color::new(1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((indexed.pixel(1, 0) - Color::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        assert!((indexed.pixel(1, 1) - Color::new(1.0, 0.0, 0.0)).length() < 1e-6);
+
+        // 16-bit RGBA, alpha is ignored
+        let rgba = read_png(&handmade_png(
+            1,
+            1,
+            16,
+            6,
+            &[],
+            &[&[0xff, 0xff, 0, 0, 0x80, 0, 0x12, 0x34]],
+        ))
+        .unwrap();
+        let pixel = rgba.pixel(0, 0);
+        assert_eq!((pixel.x, pixel.y), (1.0, 0.0));
+        assert!((pixel.z - srgb_to_linear(0x8000 as f32 / 65535.0)).abs() < 1e-6);
+
+        // a palette index past the end of PLTE
+        assert!(read_png(&handmade_png(1, 1, 8, 3, &palette, &[&[2]])).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let mut png = handmade_png(1, 1, 8, 0, &[], &[&[7]]);
+        assert!(read_png(&png).is_ok());
+        assert!(read_png(&png[..png.len() - 5]).is_err());
+        let last = png.len() - 13;
+        png[last] ^= 1; // IDAT crc
+        assert!(read_png(&png).is_err());
+        assert!(read_png(b"not a png").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit_record::{HitRecord, Hittable, HittableList},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+/// Parallelogram spanned by the edges `u` and `v` from the corner `q`
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    normal_vec: Vec3,
+    /// plane: normal_vec . p = d
+    d: f32,
+    /// n / (n . n), turns a point on the plane into (alpha, beta)
+    w: Vec3,
+    area: f32,
+    bbox: Aabb,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = u.cross(v);
+        let normal_vec = unit_vector(n);
+        let bbox = Aabb::enclosing(
+            Aabb::from_points(q, q + u + v),
+            Aabb::from_points(q + u, q + v),
+        );
+        Self {
+            q,
+            u,
+            v,
+            material,
+            normal_vec,
+            d: normal_vec.dot(q),
+            w: n / n.dot(n),
+            area: n.length(),
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        let denom = self.normal_vec.dot(r.direction);
+        // ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal_vec.dot(r.origin)) / denom;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        // 平面上の点を u, v の係数で表して、内側か判定する
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut record = HitRecord::new(p, Vec3::zero(), t, false);
+        record.u = alpha;
+        record.v = beta;
+        record.material = Some(self.material.clone());
+        record.set_face_normal_vec(r, self.normal_vec);
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    /// 面上で一様に選んだ点への方向。面積の密度を立体角に直す
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        let Some(record) = self.hit(
+            &Ray::new(origin, direction),
+            Interval::new(0.001, f32::INFINITY),
+        ) else {
+            return 0.0;
+        };
+        let distance_squared = record.t * record.t * direction.length_squared();
+        let cosine = (direction.dot(record.normal_vec) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let [u, v] = sampler.get_2d();
+        let p = self.q + (u * self.u) + (v * self.v);
+        p - origin
+    }
+}
+
+/// 対角の 2 点 a, b で決まる箱を 6 枚の quad で作る。法線は外向き
+pub fn make_box(a: Point3, b: Point3, material: Arc<dyn Material>) -> HittableList {
+    let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+    let mut sides = HittableList::new();
+    let mut side = |q: Point3, u: Vec3, v: Vec3| {
+        sides.add(Box::new(Quad::new(q, u, v, material.clone())));
+    };
+    side(Point3::new(min.x, min.y, max.z), dx, dy); // front
+    side(Point3::new(max.x, min.y, max.z), -dz, dy); // right
+    side(Point3::new(max.x, min.y, min.z), -dx, dy); // back
+    side(Point3::new(min.x, min.y, min.z), dz, dy); // left
+    side(Point3::new(min.x, max.y, max.z), dx, -dz); // top
+    side(Point3::new(min.x, min.y, min.z), dx, dz); // bottom
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian, sampler::IndependentSampler, utils::Rng, vec3::random_unit_vector,
+    };
+
+    fn grey() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn hits_inside_the_parallelogram_only() {
+        // slanted quad in the z = -2 plane
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            grey(),
+        );
+        let ray_to = |x: f32, y: f32| Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let all = Interval::new(0.001, f32::INFINITY);
+
+        let hit = quad.hit(&ray_to(0.5, 0.0), all).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-6);
+        assert!((hit.u - 0.5).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal_vec.z, 1.0);
+
+        // inside the bounding box but outside the slanted edges
+        assert!(quad.hit(&ray_to(-0.9, 0.9), all).is_none());
+        assert!(quad.hit(&ray_to(1.9, 0.9), all).is_some());
+        assert!(quad
+            .hit(&ray_to(0.5, 0.0), Interval::new(0.001, 1.0))
+            .is_none());
+    }
+
+    #[test]
+    fn box_sides_face_outwards() {
+        let sides = make_box(
+            Point3::new(1.0, 2.0, 3.0),
+            Point3::new(-1.0, 0.0, 0.0),
+            grey(),
+        );
+        assert_eq!(sides.objects.len(), 6);
+
+        // flat sides get padded boxes, so compare loosely
+        let bbox = sides.bounding_box();
+        assert!((bbox.x.min + 1.0).abs() < 1e-3);
+        assert!((bbox.y.max - 2.0).abs() < 1e-3);
+        assert!((bbox.z.max - 3.0).abs() < 1e-3);
+
+        // from outside every side is hit on its front face
+        let center = Point3::new(0.0, 1.0, 1.5);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ] {
+            let outside = Ray::new(center + 10.0 * direction, -direction);
+            let hit = sides
+                .hit(&outside, Interval::new(0.001, f32::INFINITY))
+                .unwrap();
+            assert!(hit.front_face, "{direction:?}");
+            assert_eq!(hit.normal_vec.dot(direction), 1.0);
+
+            let inside = Ray::new(center, direction);
+            let hit = sides
+                .hit(&inside, Interval::new(0.001, f32::INFINITY))
+                .unwrap();
+            assert!(!hit.front_face, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn light_pdf_integrates_to_one() {
+        let quad = Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.5, 1.5),
+            grey(),
+        );
+        let origin = Point3::new(0.3, 0.0, 0.2);
+
+        // sampled directions all point at the quad
+        let mut sampler = IndependentSampler::new(1);
+        for _ in 0..100 {
+            let direction = quad.random(origin, &mut sampler);
+            assert!(quad.pdf_value(origin, direction) > 0.0);
+        }
+
+        // E[pdf] over uniform directions is 1 / (4 pi)
+        let trials = 200_000;
+        let mut rng = Rng::new(2);
+        let sum: f32 = (0..trials)
+            .map(|_| quad.pdf_value(origin, random_unit_vector(&mut rng)))
+            .sum();
+        let integral = sum / trials as f32 * 4.0 * std::f32::consts::PI;
+        assert!((integral - 1.0).abs() < 0.05, "{integral}");
+    }
+}
@@ -4,10 +4,27 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// moment within the shutter interval the ray samples
+    pub time: f32,
+    /// key of the random numbers drawn while tracing the ray (free paths
+    /// through media), so every query of the same ray agrees
+    pub stream: u64,
 }
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+            stream: 0,
+        }
+    }
+    /// 同じ ray を別の時刻で
+    pub fn with_time(self, time: f32) -> Self {
+        Self { time, ..self }
+    }
+    pub fn with_stream(self, stream: u64) -> Self {
+        Self { stream, ..self }
     }
     pub fn at(&self, t: f32) -> Point3 {
         self.origin + t * self.direction
@@ -19,6 +36,8 @@ impl std::fmt::Debug for Ray {
         f.debug_struct("Ray")
             .field("origin", &self.origin)
             .field("direction", &self.direction)
+            .field("time", &self.time)
+            .field("stream", &self.stream)
             .finish()
     }
 }
@@ -0,0 +1,593 @@
+//! Sample generators for the camera
+//!
+//! The pixel offset, lens, shutter time, BSDF directions, light samples and
+//! Russian roulette of each camera sample are drawn from a `Sampler`, one
+//! dimension after the other.
+
+use crate::utils::{hash, Rng};
+
+/// largest f32 below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Which `Sampler` a render uses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    /// independent uniform random numbers
+    Independent,
+    /// one jittered sample per stratum of a grid
+    Stratified,
+    /// Halton sequence, shifted randomly per pixel and dimension
+    Halton,
+    /// Owen scrambled Sobol (0, 2)-sequence, shuffled per pixel and
+    /// dimension
+    Sobol,
+    /// Owen scrambled Sobol laid out over the pixels in Z-order, so the
+    /// error of neighbouring pixels cancels out (blue noise)
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const NAMES: &str = "independent, stratified, halton, sobol, blue-noise";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" | "random" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            "blue-noise" | "zsobol" => Some(SamplerKind::BlueNoise),
+            _ => None,
+        }
+    }
+
+    /// `samples_per_pixel` sets the strata of the stratified sampler and
+    /// the index layout of the blue-noise one, more samples still work
+    pub fn build(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+/// Values in [0, 1) for the dimensions of one camera sample
+pub trait Sampler {
+    /// starts sample `index` of pixel (x, y), dimensions count from 0 again
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> [f32; 2];
+}
+
+/// pixel, sample index and the next dimension, shared by the samplers
+#[derive(Clone, Copy, Default)]
+struct PixelSample {
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl PixelSample {
+    fn start(&mut self, x: u32, y: u32, index: u32) {
+        *self = Self {
+            x,
+            y,
+            index,
+            dimension: 0,
+        };
+    }
+
+    /// the current dimension, then moves on to the next one
+    fn next_dimension(&mut self) -> u32 {
+        self.dimension += 1;
+        self.dimension - 1
+    }
+}
+
+/// the top 24 bits as a float in [0, 1)
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Dimension `n` of a sample is value `n` of the sample's `Rng` stream
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    /// without `start_pixel_sample` the values continue one stream
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.f32()
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        [self.rng.f32(), self.rng.f32()]
+    }
+}
+
+/// Element `i` of a random permutation of 0..l chosen by `p` (Kensler,
+/// "Correlated Multi-Jittered Sampling"), without storing the permutation
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    // cycle walking: permute within the next power of two until in range
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+/// Jittered strata: 1D dimensions split [0, 1) into samples_per_pixel
+/// strata, 2D ones into a grid of at least that many cells. Each pixel and
+/// dimension visits the strata in its own random order.
+pub struct StratifiedSampler {
+    seed: u64,
+    strata: u32,
+    grid: (u32, u32),
+    sample: PixelSample,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let nx = (samples_per_pixel as f32).sqrt().ceil() as u32;
+        Self {
+            seed,
+            strata: samples_per_pixel,
+            grid: (nx, samples_per_pixel.div_ceil(nx)),
+            sample: PixelSample::default(),
+        }
+    }
+
+    /// (stratum, two jitter values) of the current sample in a dimension
+    /// with `count` strata. Samples past `count` start another round
+    fn stratum(&mut self, count: u32) -> (u32, [f32; 2]) {
+        let PixelSample { x, y, index, .. } = self.sample;
+        let dimension = self.sample.next_dimension();
+        let round = index / count;
+        let key = [
+            self.seed,
+            x as u64,
+            y as u64,
+            dimension as u64,
+            round as u64,
+        ];
+        let stratum = permutation_element(index % count, count, hash(&key) as u32);
+        let jitter = hash(&[hash(&key), index as u64]);
+        (
+            stratum,
+            [to_unit(jitter as u32), to_unit((jitter >> 32) as u32)],
+        )
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.sample.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (stratum, [jitter, _]) = self.stratum(self.strata);
+        ((stratum as f32 + jitter) / self.strata as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        let (nx, ny) = self.grid;
+        let (stratum, [jx, jy]) = self.stratum(nx * ny);
+        [
+            (((stratum % nx) as f32 + jx) / nx as f32).min(ONE_MINUS_EPSILON),
+            (((stratum / nx) as f32 + jy) / ny as f32).min(ONE_MINUS_EPSILON),
+        ]
+    }
+}
+
+/// bases of the Halton dimensions, later dimensions fall back to hashing
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// digits of `index` in `base` mirrored around the radix point
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0u64;
+    while index > 0 {
+        reversed = reversed * base as u64 + (index % base) as u64;
+        index /= base;
+        inv_base_n *= inv_base;
+    }
+    reversed as f64 * inv_base_n
+}
+
+/// Halton sequence, dimension d in base PRIMES[d]. Every pixel uses the
+/// same points, shifted modulo 1 by a random offset per pixel and dimension
+/// (Cranley-Patterson rotation)
+pub struct HaltonSampler {
+    seed: u64,
+    sample: PixelSample,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            sample: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.sample.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let PixelSample { x, y, index, .. } = self.sample;
+        let dimension = self.sample.next_dimension();
+        let offset = hash(&[self.seed, x as u64, y as u64, dimension as u64]);
+        let Some(&base) = PRIMES.get(dimension as usize) else {
+            return to_unit(hash(&[offset, index as u64]) as u32);
+        };
+        let shifted = radical_inverse(base, index) + to_unit(offset as u32) as f64;
+        ((shifted - shifted.floor()) as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        [self.get_1d(), self.get_1d()]
+    }
+}
+
+/// the second dimension of the Sobol sequence, the first is the bit
+/// reversed index
+fn sobol_second(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// hash based Owen scrambling (Burley, "Practical Hash-based Owen
+/// Scrambling"): every bit is flipped depending on the bits above it
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// point `index` of the 2D Sobol sequence, each coordinate Owen scrambled
+fn owen_sobol_2d(index: u32, seed: u64) -> [f32; 2] {
+    let x = owen_scramble(index.reverse_bits(), hash(&[seed, 1]) as u32);
+    let y = owen_scramble(sobol_second(index), hash(&[seed, 2]) as u32);
+    [to_unit(x), to_unit(y)]
+}
+
+/// Owen scrambled Sobol. Each dimension (or pair) takes the 2D sequence
+/// with its own scramble and its own shuffle of the sample index, so the
+/// pairs are not correlated with each other
+pub struct SobolSampler {
+    seed: u64,
+    sample: PixelSample,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            sample: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.sample.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.get_2d()[0]
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        let PixelSample { x, y, index, .. } = self.sample;
+        let dimension = self.sample.next_dimension();
+        let seed = hash(&[self.seed, x as u64, y as u64, dimension as u64]);
+        // shuffling keeps aligned blocks of indices together, the first
+        // 2^k samples of a pixel stay a (0, k, 2)-net
+        let index = owen_scramble(index, hash(&[seed, 0]) as u32);
+        owen_sobol_2d(index, seed)
+    }
+}
+
+/// the 24 orderings of a base 4 digit
+const DIGIT_PERMUTATIONS: [[u32; 4]; 24] = [
+    [0, 1, 2, 3],
+    [0, 1, 3, 2],
+    [0, 2, 1, 3],
+    [0, 2, 3, 1],
+    [0, 3, 1, 2],
+    [0, 3, 2, 1],
+    [1, 0, 2, 3],
+    [1, 0, 3, 2],
+    [1, 2, 0, 3],
+    [1, 2, 3, 0],
+    [1, 3, 0, 2],
+    [1, 3, 2, 0],
+    [2, 0, 1, 3],
+    [2, 0, 3, 1],
+    [2, 1, 0, 3],
+    [2, 1, 3, 0],
+    [2, 3, 0, 1],
+    [2, 3, 1, 0],
+    [3, 0, 1, 2],
+    [3, 0, 2, 1],
+    [3, 1, 0, 2],
+    [3, 1, 2, 0],
+    [3, 2, 0, 1],
+    [3, 2, 1, 0],
+];
+
+/// bits per axis of the Z-order index, the layout repeats every 256 x 256
+/// pixels and each of those tiles gets its own scramble
+const MORTON_BITS: u32 = 8;
+
+fn morton(x: u32, y: u32) -> u32 {
+    let spread = |v: u32| {
+        let mut v = v & 0xff;
+        v = (v | (v << 4)) & 0x0f0f;
+        v = (v | (v << 2)) & 0x3333;
+        (v | (v << 1)) & 0x5555
+    };
+    spread(x) | (spread(y) << 1)
+}
+
+/// One Owen scrambled Sobol sequence over the whole image (Ahmed and
+/// Wonka, "Screen-Space Blue-Noise Diffusion of Monte Carlo Sampling
+/// Error via Hierarchical Ordering of Pixels", as pbrt's ZSobolSampler)
+///
+/// Pixels take consecutive runs of samples in Z-order, so any aligned
+/// 2^k x 2^k block of pixels shares one well stratified set of points. The
+/// base 4 digits of the Z-order index are permuted per dimension to avoid
+/// visible structure.
+pub struct BlueNoiseSampler {
+    seed: u64,
+    log2_samples: u32,
+    sample: PixelSample,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            seed,
+            log2_samples: samples_per_pixel
+                .next_power_of_two()
+                .trailing_zeros()
+                .min(16),
+            sample: PixelSample::default(),
+        }
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.sample.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.get_2d()[0]
+    }
+
+    fn get_2d(&mut self) -> [f32; 2] {
+        let PixelSample { x, y, index, .. } = self.sample;
+        let dimension = self.sample.next_dimension() as u64;
+        // samples past 2^log2_samples and pixels in other tiles draw from a
+        // differently scrambled sequence
+        let seed = hash(&[
+            self.seed,
+            dimension,
+            (x >> MORTON_BITS) as u64,
+            (y >> MORTON_BITS) as u64,
+            (index >> self.log2_samples) as u64,
+        ]);
+
+        let code = morton(x, y);
+        let mut permuted = 0;
+        for digit in (0..MORTON_BITS).rev() {
+            let shift = 2 * digit;
+            let higher = (code as u64) >> (shift + 2);
+            let choice = hash(&[seed, higher, digit as u64]) % 24;
+            permuted |= DIGIT_PERMUTATIONS[choice as usize][(code >> shift & 3) as usize] << shift;
+        }
+        let sample_mask = (1u32 << self.log2_samples) - 1;
+        owen_sobol_2d(
+            (permuted << self.log2_samples) | (index & sample_mask),
+            seed,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// `dimensions` values of every sample of pixel (x, y)
+    fn pixel_samples(sampler: &mut dyn Sampler, x: u32, y: u32, spp: u32) -> Vec<Vec<f32>> {
+        (0..spp)
+            .map(|index| {
+                sampler.start_pixel_sample(x, y, index);
+                (0..4).flat_map(|_| sampler.get_2d()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_are_reproducible_and_in_range() {
+        for kind in KINDS {
+            let a = pixel_samples(kind.build(16, 1).as_mut(), 3, 5, 40);
+            let b = pixel_samples(kind.build(16, 1).as_mut(), 3, 5, 40);
+            let other = pixel_samples(kind.build(16, 1).as_mut(), 4, 5, 40);
+            assert!(
+                a.iter().flatten().all(|u| (0.0..1.0).contains(u)),
+                "{kind:?}"
+            );
+            if kind != SamplerKind::Independent {
+                assert_eq!(a, b, "{kind:?}");
+                assert_ne!(a, other, "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_samples_are_stratified() {
+        let cell = |u: f32, n: usize| ((u * n as f32) as usize).min(n - 1);
+        for kind in KINDS[1..].iter().copied() {
+            let mut sampler = kind.build(16, 7);
+            // Halton is only stratified in its base 2 dimension
+            let dimensions = if kind == SamplerKind::Halton { 1 } else { 6 };
+            // 1D dimensions: one sample per sixteenth
+            let mut seen = vec![[false; 16]; dimensions];
+            for index in 0..16 {
+                sampler.start_pixel_sample(10, 20, index);
+                for seen in &mut seen {
+                    seen[cell(sampler.get_1d(), 16)] = true;
+                }
+            }
+            assert!(seen.iter().flatten().all(|&s| s), "{kind:?} 1D");
+            if kind == SamplerKind::Halton {
+                continue;
+            }
+
+            // 2D dimensions: one sample per cell of a 4x4 grid
+            let mut seen = [[false; 16]; 4];
+            for sample in pixel_samples(sampler.as_mut(), 10, 20, 16) {
+                for (pair, seen) in seen.iter_mut().enumerate() {
+                    seen[cell(sample[2 * pair], 4) * 4 + cell(sample[2 * pair + 1], 4)] = true;
+                }
+            }
+            assert!(seen.iter().flatten().all(|&s| s), "{kind:?} 2D");
+        }
+    }
+
+    /// mean squared error of estimating the integral of a smooth function
+    /// over [0, 1)^2 from each pixel's samples, and of 2x2 pixel averages
+    fn integration_error(kind: SamplerKind, spp: u32) -> (f32, f32) {
+        let f = |[u, v]: [f32; 2]| (3.0 * u + 2.0 * v * v).sin() + u * v;
+        // integral of f, computed with a fine grid
+        let n = 1000;
+        let exact = (0..n * n)
+            .map(|i| {
+                f([
+                    ((i % n) as f32 + 0.5) / n as f32,
+                    ((i / n) as f32 + 0.5) / n as f32,
+                ])
+            })
+            .map(|x| x as f64)
+            .sum::<f64>()
+            / (n * n) as f64;
+
+        let mut sampler = kind.build(spp, 11);
+        let size = 16;
+        let mut estimates = vec![0.0f64; size * size];
+        for y in 0..size {
+            for x in 0..size {
+                for index in 0..spp {
+                    sampler.start_pixel_sample(x as u32, y as u32, index);
+                    estimates[y * size + x] += f(sampler.get_2d()) as f64 / spp as f64;
+                }
+            }
+        }
+        let pixel =
+            estimates.iter().map(|e| (e - exact).powi(2)).sum::<f64>() / estimates.len() as f64;
+        let mut blocks = 0.0;
+        for by in (0..size).step_by(2) {
+            for bx in (0..size).step_by(2) {
+                let block: f64 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| estimates[(by + dy) * size + bx + dx])
+                    .sum::<f64>()
+                    / 4.0;
+                blocks += (block - exact).powi(2);
+            }
+        }
+        (pixel as f32, (blocks / (size * size / 4) as f64) as f32)
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_converge_faster() {
+        let (independent, _) = integration_error(SamplerKind::Independent, 16);
+        for kind in KINDS[1..].iter().copied() {
+            let (error, _) = integration_error(kind, 16);
+            assert!(
+                error * 3.0 < independent,
+                "{kind:?}: {error} vs {independent}"
+            );
+        }
+
+        // neighbouring blue-noise pixels share one well spread point set,
+        // their errors cancel out when averaged
+        let (_, sobol) = integration_error(SamplerKind::Sobol, 16);
+        let (_, blue_noise) = integration_error(SamplerKind::BlueNoise, 16);
+        assert!(blue_noise * 10.0 < sobol, "{blue_noise} vs {sobol}");
+    }
+}
@@ -0,0 +1,1249 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use toml::{Table, Value};
+
+use crate::{
+    bvh::BvhNode,
+    camera::{AdaptiveSampling, Background, Camera},
+    filter::{Filter, FilterKind},
+    hit_record::{Hittable, HittableList},
+    image::load_image,
+    instance::Instance,
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal},
+    medium::ConstantMedium,
+    obj::{load_obj, ObjError},
+    perlin::Perlin,
+    quad::{make_box, Quad},
+    sampler::SamplerKind,
+    sphere::Sphere,
+    texture::{Checker, ImageTexture, Marble, NoiseTexture, SolidColor, Texture, Wood},
+    transform::Transform,
+    triangle::Triangle,
+    utils::{hash, mix_seed},
+    vec3::Vec3,
+};
+
+/// Scene loaded from a TOML description
+///
+/// ```toml
+/// [camera]
+/// image_width = 400
+/// lookfrom = [-2.0, 2.0, 1.0]
+///
+/// [textures.tiles]
+/// type = "checker"
+/// scale = 0.5
+/// even = [0.2, 0.3, 0.1]
+/// odd = [0.9, 0.9, 0.9]
+///
+/// [materials.ground]
+/// type = "lambertian"
+/// texture = "tiles"
+///
+/// [[objects]]
+/// type = "sphere"
+/// center = [0.0, -100.5, -1.0]
+/// radius = 100.0
+/// material = "ground"
+/// ```
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+    /// emissive objects also in `world`, sampled directly by the camera
+    pub lights: HittableList,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Syntax {
+        path: PathBuf,
+        message: String,
+    },
+    /// `item` is e.g. `camera`, `materials.glass` or `objects[3] "ball"`
+    Invalid {
+        item: String,
+        field: String,
+        message: String,
+    },
+    Model {
+        item: String,
+        source: ObjError,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            SceneError::Syntax { path, message } => write!(f, "{}: {message}", path.display()),
+            SceneError::Invalid {
+                item,
+                field,
+                message,
+            } => write!(f, "{item}: field `{field}`: {message}"),
+            SceneError::Model { item, source } => write!(f, "{item}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Model { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene(&source, path)
+}
+
+/// `path` is used for error messages and to resolve model files
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let root: Table = source
+        .parse()
+        .map_err(|e: toml::de::Error| SceneError::Syntax {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+    let root = Fields::new("scene", &root);
+    root.allow_only(&["camera", "textures", "materials", "objects"])?;
+
+    let camera = match root.table("camera")? {
+        Some(table) => parse_camera(&Fields::new("camera", table))?,
+        None => Camera::default(),
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut textures: HashMap<String, Arc<dyn Texture>> = HashMap::new();
+    if let Some(table) = root.table("textures")? {
+        for (name, value) in table {
+            let item = format!("textures.{name}");
+            let Value::Table(table) = value else {
+                return Err(SceneError::Invalid {
+                    item,
+                    field: "type".to_string(),
+                    message: "texture must be a table".to_string(),
+                });
+            };
+            let texture = parse_texture(
+                &Fields::new(&item, table),
+                base_dir,
+                texture_seed(camera.seed, name),
+            )?;
+            textures.insert(name.clone(), texture);
+        }
+    }
+
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut light_materials = HashSet::new();
+    if let Some(table) = root.table("materials")? {
+        for (name, value) in table {
+            let item = format!("materials.{name}");
+            let Value::Table(table) = value else {
+                return Err(SceneError::Invalid {
+                    item,
+                    field: "type".to_string(),
+                    message: "material must be a table".to_string(),
+                });
+            };
+            let material = parse_material(&Fields::new(&item, table), &textures)?;
+            materials.insert(name.clone(), material);
+            if table.get("type").and_then(Value::as_str) == Some("diffuse_light") {
+                light_materials.insert(name.as_str());
+            }
+        }
+    }
+
+    let mut models = HashMap::new();
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    for (index, value) in root.array("objects")?.unwrap_or(&vec![]).iter().enumerate() {
+        let item = match value.get("name").and_then(Value::as_str) {
+            Some(name) => format!("objects[{index}] \"{name}\""),
+            None => format!("objects[{index}]"),
+        };
+        let Value::Table(table) = value else {
+            return Err(SceneError::Invalid {
+                item,
+                field: "type".to_string(),
+                message: "object must be a table".to_string(),
+            });
+        };
+        let fields = Fields::new(&item, table);
+        let mut object = parse_object(&fields, &materials, base_dir, &mut models)?;
+        // 光源として直接サンプルできるのは、動かない素の sphere / quad / box だけ
+        let mut samplable = matches!(
+            table.get("type").and_then(Value::as_str),
+            Some("sphere" | "quad" | "box")
+        ) && !table.contains_key("center_end")
+            && table
+                .get("material")
+                .and_then(Value::as_str)
+                .is_some_and(|name| light_materials.contains(name));
+        if let Some(transform) = parse_transform(&fields)? {
+            object = Arc::new(Instance::new(object, transform));
+            samplable = false;
+        }
+        // the shape becomes the boundary of a fog made of its material
+        if let Some(density) = fields.positive_f32("density")? {
+            let phase_function = object_material(&fields, &materials)?;
+            object = Arc::new(ConstantMedium::new(object, density, phase_function));
+            samplable = false;
+        }
+        if samplable {
+            lights.add(Box::new(object.clone()));
+        }
+        world.add(Box::new(object));
+    }
+
+    Ok(Scene {
+        camera,
+        world,
+        lights,
+    })
+}
+
+fn parse_camera(fields: &Fields) -> Result<Camera, SceneError> {
+    fields.allow_only(&[
+        "aspect_ratio",
+        "image_width",
+        "vfov",
+        "samples_per_pixel",
+        "adaptive_error",
+        "min_samples_per_pixel",
+        "max_depth",
+        "roulette_depth",
+        "sampler",
+        "filter",
+        "filter_radius",
+        "lookfrom",
+        "lookat",
+        "vup",
+        "defocus_angle",
+        "focus_distance",
+        "background",
+        "shutter_open",
+        "shutter_close",
+    ])?;
+    let defaults = Camera::default();
+
+    let mut camera = Camera::new(
+        fields
+            .positive_f32("aspect_ratio")?
+            .unwrap_or(defaults.aspect_ratio),
+        fields
+            .positive_u32("image_width")?
+            .unwrap_or(defaults.image_width),
+        fields.positive_f32("vfov")?.unwrap_or(defaults.vfov),
+        fields
+            .positive_u32("samples_per_pixel")?
+            .map_or(defaults.samples_per_pixel, |n| n as f32),
+        fields
+            .positive_u32("max_depth")?
+            .unwrap_or(defaults.max_depth),
+        fields.vec3("lookfrom")?.unwrap_or(defaults.lookfrom),
+        fields.vec3("lookat")?.unwrap_or(defaults.lookat),
+        fields.vec3("vup")?.unwrap_or(defaults.vup),
+    );
+    if camera.pixel_count().is_none() {
+        return Err(fields.invalid(
+            "image_width",
+            format!(
+                "makes a {}x{} image, too many pixels",
+                camera.image_width,
+                camera.image_height()
+            ),
+        ));
+    }
+    if let Some(depth) = fields.positive_u32("roulette_depth")? {
+        camera.roulette_depth = depth;
+    }
+    let min_samples = fields.positive_u32("min_samples_per_pixel")?;
+    match (fields.positive_f32("adaptive_error")?, min_samples) {
+        (Some(max_error), min_samples) => {
+            camera.adaptive = Some(AdaptiveSampling {
+                min_samples: min_samples.unwrap_or(AdaptiveSampling::DEFAULT_MIN_SAMPLES),
+                max_error,
+            });
+        }
+        (None, Some(_)) => {
+            return Err(fields.invalid(
+                "min_samples_per_pixel",
+                "only applies with adaptive_error".to_string(),
+            ));
+        }
+        (None, None) => {}
+    }
+    if let Some(name) = fields.optional_str("sampler")? {
+        camera.sampler = SamplerKind::from_name(name).ok_or_else(|| {
+            fields.invalid(
+                "sampler",
+                format!(
+                    "unknown sampler `{name}`, expected one of {}",
+                    SamplerKind::NAMES
+                ),
+            )
+        })?;
+    }
+    if let Some(name) = fields.optional_str("filter")? {
+        let kind = FilterKind::from_name(name).ok_or_else(|| {
+            fields.invalid(
+                "filter",
+                format!(
+                    "unknown filter `{name}`, expected one of {}",
+                    FilterKind::NAMES
+                ),
+            )
+        })?;
+        camera.filter = Filter::new(kind);
+    }
+    if let Some(radius) = fields.positive_f32("filter_radius")? {
+        let kind = camera.filter.kind;
+        if radius < kind.min_radius() {
+            return Err(fields.invalid(
+                "filter_radius",
+                format!(
+                    "must be at least {} pixels for the {} filter, got {radius}",
+                    kind.min_radius(),
+                    kind.name()
+                ),
+            ));
+        }
+        camera.filter.radius = radius;
+    }
+    if let Some(angle) = fields.f32("defocus_angle")? {
+        camera.defocus_angle = angle;
+    }
+    if let Some(distance) = fields.positive_f32("focus_distance")? {
+        camera.focus_distance = distance;
+    }
+    // without it the sky gradient lights the scene
+    if let Some(color) = fields.vec3("background")? {
+        camera.background = Background::Solid(color);
+    }
+    camera.shutter_open = fields.f32("shutter_open")?.unwrap_or(0.0);
+    camera.shutter_close = fields.f32("shutter_close")?.unwrap_or(camera.shutter_open);
+    if camera.shutter_close < camera.shutter_open {
+        return Err(fields.invalid(
+            "shutter_close",
+            "must not be before shutter_open".to_string(),
+        ));
+    }
+    Ok(camera)
+}
+
+/// seed of a noise texture without its own `seed`, so that differently
+/// named textures get different patterns
+fn texture_seed(camera_seed: u64, name: &str) -> u64 {
+    let name: Vec<u64> = name.bytes().map(u64::from).collect();
+    mix_seed(camera_seed, hash(&name))
+}
+
+/// noise textures build their gradients from `seed` (default
+/// `default_seed`), the same seed gives the same pattern in every render
+fn parse_texture(
+    fields: &Fields,
+    base_dir: &Path,
+    default_seed: u64,
+) -> Result<Arc<dyn Texture>, SceneError> {
+    let noise = || -> Result<Perlin, SceneError> {
+        Ok(Perlin::new(fields.seed("seed")?.unwrap_or(default_seed)))
+    };
+    let scale = || -> Result<f32, SceneError> { Ok(fields.positive_f32("scale")?.unwrap_or(1.0)) };
+    match fields.required_str("type")? {
+        "solid" => {
+            fields.allow_only(&["type", "color"])?;
+            Ok(Arc::new(SolidColor::new(fields.required_vec3("color")?)))
+        }
+        "checker" => {
+            fields.allow_only(&["type", "scale", "even", "odd"])?;
+            Ok(Arc::new(Checker::from_colors(
+                fields.positive_f32("scale")?.unwrap_or(1.0),
+                fields.required_vec3("even")?,
+                fields.required_vec3("odd")?,
+            )))
+        }
+        "image" => {
+            fields.allow_only(&["type", "path"])?;
+            let path = base_dir.join(fields.required_str("path")?);
+            let image = load_image(&path).map_err(|source| SceneError::Io { path, source })?;
+            Ok(Arc::new(ImageTexture::new(image)))
+        }
+        "noise" => {
+            fields.allow_only(&["type", "scale", "seed"])?;
+            Ok(Arc::new(NoiseTexture::new(noise()?, scale()?)))
+        }
+        "marble" => {
+            fields.allow_only(&["type", "scale", "seed", "color"])?;
+            Ok(Arc::new(Marble::new(
+                noise()?,
+                scale()?,
+                fields.vec3("color")?.unwrap_or(Vec3::new(1.0, 1.0, 1.0)),
+            )))
+        }
+        "wood" => {
+            fields.allow_only(&["type", "scale", "seed", "light", "dark"])?;
+            Ok(Arc::new(Wood::new(
+                noise()?,
+                scale()?,
+                fields.required_vec3("light")?,
+                fields.required_vec3("dark")?,
+            )))
+        }
+        other => Err(fields.invalid(
+            "type",
+            format!(
+                "unknown texture type `{other}` \
+                 (expected solid, checker, image, noise, marble or wood)"
+            ),
+        )),
+    }
+}
+
+fn parse_material(
+    fields: &Fields,
+    textures: &HashMap<String, Arc<dyn Texture>>,
+) -> Result<Arc<dyn Material>, SceneError> {
+    // `albedo` か `texture` のどちらか一方
+    let albedo = || -> Result<Arc<dyn Texture>, SceneError> {
+        match (fields.vec3("albedo")?, fields.optional_str("texture")?) {
+            (Some(albedo), None) => Ok(Arc::new(SolidColor::new(albedo))),
+            (None, Some(name)) => textures
+                .get(name)
+                .cloned()
+                .ok_or_else(|| fields.invalid("texture", format!("unknown texture `{name}`"))),
+            (Some(_), Some(_)) => Err(fields.invalid(
+                "texture",
+                "give either `albedo` or `texture`, not both".to_string(),
+            )),
+            (None, None) => Err(fields.invalid("albedo", "missing required field".to_string())),
+        }
+    };
+    match fields.required_str("type")? {
+        "lambertian" => {
+            fields.allow_only(&["type", "albedo", "texture"])?;
+            Ok(Arc::new(Lambertian::with_texture(albedo()?)))
+        }
+        "metal" => {
+            fields.allow_only(&["type", "albedo", "texture", "fuzz"])?;
+            let fuzz = fields.f32("fuzz")?.unwrap_or(0.0);
+            if !(0.0..=1.0).contains(&fuzz) {
+                return Err(fields.invalid("fuzz", format!("must be between 0 and 1, got {fuzz}")));
+            }
+            Ok(Arc::new(Metal::with_texture(albedo()?, fuzz)))
+        }
+        "dielectric" => {
+            fields.allow_only(&["type", "refraction_index"])?;
+            Ok(Arc::new(Dielectric::new(
+                fields.required_positive_f32("refraction_index")?,
+            )))
+        }
+        "diffuse_light" => {
+            fields.allow_only(&["type", "emit"])?;
+            Ok(Arc::new(DiffuseLight::new(fields.required_vec3("emit")?)))
+        }
+        "isotropic" => {
+            fields.allow_only(&["type", "albedo", "texture"])?;
+            Ok(Arc::new(Isotropic::with_texture(albedo()?)))
+        }
+        other => Err(fields.invalid(
+            "type",
+            format!(
+                "unknown material type `{other}` \
+                 (expected lambertian, metal, dielectric, diffuse_light or isotropic)"
+            ),
+        )),
+    }
+}
+
+/// fields every object type accepts, see `parse_transform`
+const TRANSFORM_FIELDS: [&str; 3] = ["scale", "rotate", "translate"];
+
+/// A model file loaded once is shared by every object that uses it
+type ModelCache = HashMap<(PathBuf, Option<String>), Arc<dyn Hittable>>;
+
+fn object_material(
+    fields: &Fields,
+    materials: &HashMap<String, Arc<dyn Material>>,
+) -> Result<Arc<dyn Material>, SceneError> {
+    let name = fields.required_str("material")?;
+    materials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| fields.invalid("material", format!("unknown material `{name}`")))
+}
+
+fn parse_object(
+    fields: &Fields,
+    materials: &HashMap<String, Arc<dyn Material>>,
+    base_dir: &Path,
+    models: &mut ModelCache,
+) -> Result<Arc<dyn Hittable>, SceneError> {
+    let allow_only = |known: &[&str]| fields.allow_only(&[known, &TRANSFORM_FIELDS].concat());
+    let material = |fields: &Fields| object_material(fields, materials);
+
+    let object: Arc<dyn Hittable> = match fields.required_str("type")? {
+        "sphere" => {
+            allow_only(&[
+                "type",
+                "name",
+                "center",
+                "center_end",
+                "radius",
+                "material",
+                "density",
+            ])?;
+            // `center_end` is where the sphere is at time 1
+            let center = fields.required_vec3("center")?;
+            Arc::new(Sphere::moving(
+                center,
+                fields.vec3("center_end")?.unwrap_or(center),
+                fields.required_positive_f32("radius")?,
+                material(fields)?,
+            ))
+        }
+        "triangle" => {
+            allow_only(&["type", "name", "vertices", "material"])?;
+            let vertices = fields.required_vec3_list("vertices")?;
+            let [p0, p1, p2] = vertices[..] else {
+                return Err(fields.invalid(
+                    "vertices",
+                    format!("expected 3 vertices, found {}", vertices.len()),
+                ));
+            };
+            Arc::new(Triangle::new(p0, p1, p2, material(fields)?))
+        }
+        "quad" => {
+            allow_only(&["type", "name", "corner", "u", "v", "material"])?;
+            Arc::new(Quad::new(
+                fields.required_vec3("corner")?,
+                fields.required_vec3("u")?,
+                fields.required_vec3("v")?,
+                material(fields)?,
+            ))
+        }
+        "box" => {
+            allow_only(&["type", "name", "min", "max", "material", "density"])?;
+            Arc::new(make_box(
+                fields.required_vec3("min")?,
+                fields.required_vec3("max")?,
+                material(fields)?,
+            ))
+        }
+        "obj" => {
+            allow_only(&["type", "name", "path", "group"])?;
+            let path = base_dir.join(fields.required_str("path")?);
+            let group = fields.optional_str("group")?;
+            let key = (path, group.map(str::to_string));
+            if let Some(model) = models.get(&key) {
+                return Ok(model.clone());
+            }
+
+            let mut model = load_obj(&key.0).map_err(|source| SceneError::Model {
+                item: fields.item.clone(),
+                source,
+            })?;
+            // only load one `g` / `o` group of the file
+            if let Some(group) = group {
+                let Some(found) = model.groups.iter().find(|g| g.name == group) else {
+                    return Err(fields.invalid("group", format!("no group named `{group}`")));
+                };
+                model.mesh.faces = model.mesh.faces[found.faces.clone()].to_vec();
+            }
+            if model.mesh.faces.is_empty() {
+                return Err(fields.invalid("path", "model has no faces".to_string()));
+            }
+            let model: Arc<dyn Hittable> = Arc::new(BvhNode::new(model.mesh.into_list()));
+            models.insert(key, model.clone());
+            model
+        }
+        other => {
+            return Err(fields.invalid(
+                "type",
+                format!(
+                    "unknown object type `{other}` (expected sphere, triangle, quad, box or obj)"
+                ),
+            ));
+        }
+    };
+    Ok(object)
+}
+
+/// `scale` (a number or per axis), `rotate` (degrees around x, then y,
+/// then z) and `translate`, applied in that order
+fn parse_transform(fields: &Fields) -> Result<Option<Transform>, SceneError> {
+    if !TRANSFORM_FIELDS
+        .iter()
+        .any(|f| fields.table.contains_key(*f))
+    {
+        return Ok(None);
+    }
+    let mut transform = Transform::identity();
+    if let Some(value) = fields.table.get("scale") {
+        let scale = match value {
+            Value::Array(_) => fields.to_vec3("scale", value)?,
+            _ => {
+                let s = fields.number("scale", value)?;
+                Vec3::new(s, s, s)
+            }
+        };
+        if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            return Err(fields.invalid("scale", "must not be zero".to_string()));
+        }
+        transform = transform.scale(scale);
+    }
+    if let Some(degrees) = fields.vec3("rotate")? {
+        transform = transform
+            .rotate_x(degrees.x)
+            .rotate_y(degrees.y)
+            .rotate_z(degrees.z);
+    }
+    if let Some(offset) = fields.vec3("translate")? {
+        transform = transform.translate(offset);
+    }
+    Ok(Some(transform))
+}
+
+/// 型チェック付きで table から値を取り出す
+struct Fields<'a> {
+    item: String,
+    table: &'a Table,
+}
+
+impl<'a> Fields<'a> {
+    fn new(item: &str, table: &'a Table) -> Self {
+        Self {
+            item: item.to_string(),
+            table,
+        }
+    }
+
+    fn invalid(&self, field: &str, message: String) -> SceneError {
+        SceneError::Invalid {
+            item: self.item.clone(),
+            field: field.to_string(),
+            message,
+        }
+    }
+
+    fn type_error(&self, field: &str, expected: &str, found: &Value) -> SceneError {
+        self.invalid(
+            field,
+            format!("expected {expected}, found {}", found.type_str()),
+        )
+    }
+
+    /// typo を見逃さないように、知らない field はエラーにする
+    fn allow_only(&self, known: &[&str]) -> Result<(), SceneError> {
+        match self.table.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(self.invalid(key, "unknown field".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn required<T>(&self, field: &str, value: Option<T>) -> Result<T, SceneError> {
+        value.ok_or_else(|| self.invalid(field, "missing required field".to_string()))
+    }
+
+    fn table(&self, field: &str) -> Result<Option<&'a Table>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(Value::Table(table)) => Ok(Some(table)),
+            Some(other) => Err(self.type_error(field, "a table", other)),
+        }
+    }
+
+    fn array(&self, field: &str) -> Result<Option<&'a Vec<Value>>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(Value::Array(array)) => Ok(Some(array)),
+            Some(other) => Err(self.type_error(field, "an array", other)),
+        }
+    }
+
+    fn optional_str(&self, field: &str) -> Result<Option<&'a str>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(other) => Err(self.type_error(field, "a string", other)),
+        }
+    }
+
+    fn required_str(&self, field: &str) -> Result<&'a str, SceneError> {
+        let value = self.optional_str(field)?;
+        self.required(field, value)
+    }
+
+    fn f32(&self, field: &str) -> Result<Option<f32>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(value) => self.number(field, value).map(Some),
+        }
+    }
+
+    fn positive_f32(&self, field: &str) -> Result<Option<f32>, SceneError> {
+        match self.f32(field)? {
+            Some(x) if x <= 0.0 => Err(self.invalid(field, format!("must be positive, got {x}"))),
+            x => Ok(x),
+        }
+    }
+
+    fn required_positive_f32(&self, field: &str) -> Result<f32, SceneError> {
+        let value = self.positive_f32(field)?;
+        self.required(field, value)
+    }
+
+    fn seed(&self, field: &str) -> Result<Option<u64>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(Value::Integer(n)) if *n >= 0 => Ok(Some(*n as u64)),
+            Some(Value::Integer(n)) => {
+                Err(self.invalid(field, format!("must not be negative, got {n}")))
+            }
+            Some(other) => Err(self.type_error(field, "an integer", other)),
+        }
+    }
+
+    fn positive_u32(&self, field: &str) -> Result<Option<u32>, SceneError> {
+        match self.table.get(field) {
+            None => Ok(None),
+            Some(Value::Integer(n)) if *n > 0 && *n <= u32::MAX as i64 => Ok(Some(*n as u32)),
+            Some(Value::Integer(n)) => {
+                Err(self.invalid(field, format!("must be a positive integer, got {n}")))
+            }
+            Some(other) => Err(self.type_error(field, "an integer", other)),
+        }
+    }
+
+    /// TOML allows `nan` and `inf`, neither makes sense in a scene
+    fn number(&self, field: &str, value: &Value) -> Result<f32, SceneError> {
+        let x = match value {
+            Value::Float(x) => *x as f32,
+            Value::Integer(n) => *n as f32,
+            other => return Err(self.type_error(field, "a number", other)),
+        };
+        if !x.is_finite() {
+            return Err(self.invalid(field, format!("must be a finite number, got {x}")));
+        }
+        Ok(x)
+    }
+
+    fn to_vec3(&self, field: &str, value: &Value) -> Result<Vec3, SceneError> {
+        match value {
+            Value::Array(array) if array.len() == 3 => Ok(Vec3::new(
+                self.number(field, &array[0])?,
+                self.number(field, &array[1])?,
+                self.number(field, &array[2])?,
+            )),
+            Value::Array(array) => Err(self.invalid(
+                field,
+                format!("expected 3 components, found {}", array.len()),
+            )),
+            other => Err(self.type_error(field, "an array of 3 numbers", other)),
+        }
+    }
+
+    fn vec3(&self, field: &str) -> Result<Option<Vec3>, SceneError> {
+        self.table
+            .get(field)
+            .map(|value| self.to_vec3(field, value))
+            .transpose()
+    }
+
+    fn required_vec3(&self, field: &str) -> Result<Vec3, SceneError> {
+        let value = self.vec3(field)?;
+        self.required(field, value)
+    }
+
+    fn required_vec3_list(&self, field: &str) -> Result<Vec<Vec3>, SceneError> {
+        let array = self.array(field)?;
+        self.required(field, array)?
+            .iter()
+            .map(|value| self.to_vec3(field, value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hit_record::Hittable, interval::Interval, ray::Ray, sampler::IndependentSampler,
+        vec3::Point3,
+    };
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, Path::new("test.toml"))
+    }
+
+    fn invalid(source: &str) -> (String, String) {
+        match parse(source) {
+            Err(SceneError::Invalid { item, field, .. }) => (item, field),
+            Err(other) => panic!("unexpected error: {other}"),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn parses_camera_materials_and_objects() {
+        let scene = parse(
+            r#"
+            [camera]
+            image_width = 64
+            max_depth = 5
+            roulette_depth = 2
+            adaptive_error = 0.05
+            min_samples_per_pixel = 8
+            sampler = "blue-noise"
+            filter = "gaussian"
+            filter_radius = 2
+            lookfrom = [0, 0, 5]
+            lookat = [0, 0, 0]
+            defocus_angle = 0.5
+            background = [0, 0, 0]
+            shutter_close = 1.0
+
+            [materials.red]
+            type = "lambertian"
+            albedo = [0.8, 0.1, 0.1]
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            center_end = [0, 5, 0]
+            radius = 1
+            material = "red"
+
+            [[objects]]
+            type = "triangle"
+            vertices = [[-1, -1, -3], [1, -1, -3], [0, 1, -3]]
+            material = "lamp"
+
+            [[objects]]
+            type = "quad"
+            corner = [-5, -5, -10]
+            u = [10, 0, 0]
+            v = [0, 10, 0]
+            material = "red"
+
+            [[objects]]
+            type = "box"
+            min = [3, 3, 3]
+            max = [4, 4, 4]
+            material = "red"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.camera.image_width, 64);
+        assert_eq!(scene.camera.max_depth, 5);
+        assert_eq!(scene.camera.roulette_depth, 2);
+        assert_eq!(
+            scene.camera.adaptive,
+            Some(AdaptiveSampling {
+                min_samples: 8,
+                max_error: 0.05,
+            })
+        );
+        assert_eq!(scene.camera.sampler, SamplerKind::BlueNoise);
+        assert_eq!(
+            scene.camera.filter,
+            Filter {
+                kind: FilterKind::Gaussian,
+                radius: 2.0,
+            }
+        );
+        assert_eq!(scene.camera.defocus_angle, 0.5);
+        assert_eq!(scene.camera.lookfrom.z, 5.0);
+        assert!(matches!(scene.camera.background, Background::Solid(c) if c.length() == 0.0));
+        assert_eq!(
+            (scene.camera.shutter_open, scene.camera.shutter_close),
+            (0.0, 1.0)
+        );
+        assert_eq!(scene.world.objects.len(), 4);
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = scene
+            .world
+            .hit(&ray, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        // by time 1 the sphere has moved out of the way, the lamp is next
+        let later = ray.with_time(1.0);
+        let hit = scene
+            .world
+            .hit(&later, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!((hit.t - 8.0).abs() < 1e-5);
+
+        // behind the sphere, the triangle glows
+        let hit = scene
+            .world
+            .hit(&ray, Interval::new(7.0, f32::INFINITY))
+            .unwrap();
+        let emitted = hit.material.as_ref().unwrap().emitted(&ray, &hit);
+        assert_eq!(emitted.y, 4.0);
+
+        // then the back quad
+        let hit = scene
+            .world
+            .hit(&ray, Interval::new(8.5, f32::INFINITY))
+            .unwrap();
+        assert!((hit.t - 15.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn places_objects_with_transforms() {
+        let scene = parse(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "box"
+            min = [-1, -1, -1]
+            max = [1, 1, 1]
+            material = "white"
+            scale = 2
+            rotate = [0, 45, 0]
+            translate = [0, 0, -10]
+            "#,
+        )
+        .unwrap();
+
+        // the cube's edge now points at the camera
+        let hit = scene
+            .world
+            .hit(
+                &Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0)),
+                Interval::new(0.001, f32::INFINITY),
+            )
+            .unwrap();
+        assert!((hit.t - (10.0 - 2.0 * 2f32.sqrt())).abs() < 1e-3);
+        assert!((hit.p.z + hit.t).abs() < 1e-4);
+
+        let (item, field) = invalid(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [0.7, 0.7, 0.7]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "white"
+            scale = [1, 0, 1]
+            "#,
+        );
+        assert_eq!((item.as_str(), field.as_str()), ("objects[0]", "scale"));
+    }
+
+    #[test]
+    fn textures_feed_materials() {
+        let scene = parse(
+            r#"
+            [textures.tiles]
+            type = "checker"
+            scale = 1.0
+            even = [1, 1, 1]
+            odd = [0, 0, 0]
+
+            [materials.floor]
+            type = "lambertian"
+            texture = "tiles"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "floor"
+            "#,
+        )
+        .unwrap();
+        let albedo_at = |x: f32| {
+            let ray = Ray::new(Vec3::new(x, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = scene
+                .world
+                .hit(&ray, Interval::new(0.001, f32::INFINITY))
+                .unwrap();
+            let (_, attenuation) = hit
+                .material
+                .as_ref()
+                .unwrap()
+                .scatter(&ray, &hit, &mut IndependentSampler::new(1))
+                .unwrap();
+            attenuation
+        };
+        assert!((albedo_at(0.5) - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-6);
+        assert!(albedo_at(-0.5).length() < 1e-6);
+
+        let (item, field) = invalid(
+            r#"
+            [materials.floor]
+            type = "lambertian"
+            texture = "tiles"
+            "#,
+        );
+        assert_eq!(
+            (item.as_str(), field.as_str()),
+            ("materials.floor", "texture")
+        );
+
+        let (_, field) = invalid(
+            r#"
+            [textures.red]
+            type = "solid"
+            color = [1, 0, 0]
+
+            [materials.floor]
+            type = "metal"
+            albedo = [1, 1, 1]
+            texture = "red"
+            "#,
+        );
+        assert_eq!(field, "texture");
+
+        assert!(matches!(
+            parse("[textures.photo]\ntype = \"image\"\npath = \"missing.png\"\n"),
+            Err(SceneError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn unseeded_noise_textures_differ() {
+        let source = r#"
+            [textures.a]
+            type = "noise"
+
+            [textures.b]
+            type = "marble"
+
+            [textures.c]
+            type = "noise"
+            seed = 7
+        "#;
+        let table: Table = source.parse().unwrap();
+        let texture = |name: &str, camera_seed: u64| {
+            let Value::Table(fields) = &table["textures"][name] else {
+                panic!("expected a table");
+            };
+            let fields = Fields::new(name, fields);
+            parse_texture(&fields, Path::new(""), texture_seed(camera_seed, name)).unwrap()
+        };
+        let pattern = |texture: Arc<dyn Texture>| {
+            (0..16)
+                .map(|i| {
+                    texture
+                        .value(0.0, 0.0, Point3::new(i as f32 * 0.37, 0.5, 0.25))
+                        .x
+                })
+                .collect::<Vec<_>>()
+        };
+        let noise = |seed| pattern(Arc::new(NoiseTexture::new(Perlin::new(seed), 1.0)));
+
+        // reproducible, and not the pattern every unseeded texture used to share
+        let a = pattern(texture("a", 5464));
+        assert_eq!(a, pattern(texture("a", 5464)));
+        assert_ne!(a, noise(0));
+        assert_eq!(a, noise(texture_seed(5464, "a")));
+        assert_ne!(a, pattern(texture("a", 1)));
+        assert_ne!(texture_seed(5464, "a"), texture_seed(5464, "b"));
+
+        // an explicit seed ignores the camera
+        assert_eq!(pattern(texture("c", 5464)), noise(7));
+        assert_eq!(pattern(texture("c", 1)), noise(7));
+    }
+
+    #[test]
+    fn density_turns_shapes_into_fog() {
+        let scene = parse(
+            r#"
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.2, 0.2, 0.2]
+
+            [[objects]]
+            type = "box"
+            min = [-1, -1, -1]
+            max = [1, 1, 1]
+            material = "smoke"
+            density = 1000.0
+            rotate = [0, 45, 0]
+            "#,
+        )
+        .unwrap();
+        // thick enough to scatter right where the rotated box starts
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = scene
+            .world
+            .hit(&ray, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        assert!((hit.t - (5.0 - 2f32.sqrt())).abs() < 0.02, "{}", hit.t);
+
+        let (_, field) = invalid(
+            r#"
+            [materials.smoke]
+            type = "isotropic"
+            albedo = [0.2, 0.2, 0.2]
+
+            [[objects]]
+            type = "quad"
+            corner = [0, 0, 0]
+            u = [1, 0, 0]
+            v = [0, 1, 0]
+            material = "smoke"
+            density = 0.5
+            "#,
+        );
+        assert_eq!(field, "density");
+    }
+
+    #[test]
+    fn errors_name_the_object_and_field() {
+        let materials = r#"
+            [materials.red]
+            type = "lambertian"
+            albedo = [0.8, 0.1, 0.1]
+        "#;
+
+        let (item, field) = invalid(&format!(
+            r#"{materials}
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "red"
+
+            [[objects]]
+            name = "ball"
+            type = "sphere"
+            center = [0, 0]
+            radius = 1
+            material = "red"
+            "#
+        ));
+        assert_eq!(item, "objects[1] \"ball\"");
+        assert_eq!(field, "center");
+
+        let (item, field) = invalid(&format!(
+            r#"{materials}
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = -1
+            material = "red"
+            "#
+        ));
+        assert_eq!((item.as_str(), field.as_str()), ("objects[0]", "radius"));
+
+        let (item, field) = invalid(&format!(
+            r#"{materials}
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "blue"
+            "#
+        ));
+        assert_eq!((item.as_str(), field.as_str()), ("objects[0]", "material"));
+
+        let (item, field) = invalid(
+            r#"
+            [materials.shiny]
+            type = "metal"
+            albedo = [1, 1, 1]
+            fuz = 0.1
+            "#,
+        );
+        assert_eq!((item.as_str(), field.as_str()), ("materials.shiny", "fuz"));
+
+        let (item, field) = invalid("[camera]\nimage_width = \"wide\"\n");
+        assert_eq!((item.as_str(), field.as_str()), ("camera", "image_width"));
+
+        // TOML has nan and inf, and f64 values beyond the f32 range
+        for (camera, field) in [
+            ("filter_radius = nan", "filter_radius"),
+            ("aspect_ratio = inf", "aspect_ratio"),
+            ("vfov = 1e100", "vfov"),
+            ("lookat = [0, -inf, 0]", "lookat"),
+        ] {
+            assert_eq!(invalid(&format!("[camera]\n{camera}\n")).1, field);
+        }
+        let (item, field) = invalid(&format!(
+            r#"{materials}
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = nan
+            material = "red"
+            "#
+        ));
+        assert_eq!((item.as_str(), field.as_str()), ("objects[0]", "radius"));
+        for fuzz in ["-0.1", "1.5"] {
+            let (item, field) = invalid(&format!(
+                "[materials.shiny]\ntype = \"metal\"\nalbedo = [1, 1, 1]\nfuzz = {fuzz}\n"
+            ));
+            assert_eq!((item.as_str(), field.as_str()), ("materials.shiny", "fuzz"));
+        }
+
+        let (_, field) = invalid("[camera]\nimage_width = 100000\naspect_ratio = 1.5\n");
+        assert_eq!(field, "image_width");
+
+        let (_, field) = invalid("[camera]\nmin_samples_per_pixel = 4\n");
+        assert_eq!(field, "min_samples_per_pixel");
+
+        let (_, field) = invalid("[camera]\nfilter_radius = 0.3\n");
+        assert_eq!(field, "filter_radius");
+        let (_, field) = invalid("[camera]\nfilter = \"mitchell\"\nfilter_radius = 0.75\n");
+        assert_eq!(field, "filter_radius");
+    }
+
+    #[test]
+    fn shipped_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "toml")
+                && let Err(err) = load_scene(&path)
+            {
+                panic!("{err}");
+            }
+        }
+
+        // the ceiling light is sampled directly, the rest only when hit
+        let cornell = load_scene(&dir.join("cornell_box.toml")).unwrap();
+        assert_eq!(cornell.lights.objects.len(), 1);
+        let three_spheres = load_scene(&dir.join("three_spheres.toml")).unwrap();
+        assert!(three_spheres.lights.objects.is_empty());
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert!(matches!(
+            parse("[camera\nimage_width = 1"),
+            Err(SceneError::Syntax { .. })
+        ));
+    }
+}
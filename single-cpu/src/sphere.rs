@@ -1,32 +1,71 @@
 use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     hit_record::{HitRecord, Hittable},
     interval::Interval,
     material::Material,
+    onb::Onb,
     ray::Ray,
-    vec3::Point3,
+    sampler::Sampler,
+    vec3::{sample_unit_vector, Point3, Vec3},
 };
 
 pub struct Sphere {
+    /// center at time 0
     pub center: Point3,
     pub radius: f32,
     pub material: Arc<dyn Material>,
+    /// distance the center moves between time 0 and time 1
+    pub motion: Vec3,
+    bbox: Aabb,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: f32, material: Arc<dyn Material>) -> Self {
+        Self::moving(center, center, radius, material)
+    }
+
+    /// 時刻 0 で center0、時刻 1 で center1 にいる球。間は直線で動く
+    pub fn moving(
+        center0: Point3,
+        center1: Point3,
+        radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let radius = radius.max(0f32);
+        let rvec = Vec3::new(radius, radius, radius);
         Self {
-            center,
-            radius: radius.max(0f32),
+            center: center0,
+            radius,
             material,
+            motion: center1 - center0,
+            bbox: Aabb::enclosing(
+                Aabb::from_points(center0 - rvec, center0 + rvec),
+                Aabb::from_points(center1 - rvec, center1 + rvec),
+            ),
         }
     }
+
+    pub fn center_at(&self, time: f32) -> Point3 {
+        self.center + time * self.motion
+    }
+
+    /// 単位球上の点 -> (u, v)。u は -x から y 軸まわりに一周、v は -y から +y へ
+    fn uv(p: Point3) -> (f32, f32) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + std::f32::consts::PI;
+        (
+            phi / (2.0 * std::f32::consts::PI),
+            theta / std::f32::consts::PI,
+        )
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
-        let oc = self.center - r.origin;
+        let center = self.center_at(r.time);
+        let oc = center - r.origin;
         let a = r.direction.length_squared();
         let h = r.direction.dot(oc);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -50,17 +89,143 @@ impl Hittable for Sphere {
             normal_vec: Point3::zero(),
             material: None,
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
         };
 
         record.t = root;
         record.p = r.at(record.t);
-        record.normal_vec = (record.p - self.center) / self.radius;
+        record.normal_vec = (record.p - center) / self.radius;
         record.material = Some(self.material.clone());
 
-        let outward_normal_vec = (record.p - self.center) / self.radius;
+        let outward_normal_vec = (record.p - center) / self.radius;
+        (record.u, record.v) = Self::uv(outward_normal_vec);
         record.set_face_normal_vec(r, outward_normal_vec);
         Some(record)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    /// 外からは球が見える円錐の中で一様、中からは全方向で一様。
+    /// 動く球は時刻 0 の位置で選ぶ
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * std::f32::consts::PI);
+        }
+        if self
+            .hit(
+                &Ray::new(origin, direction),
+                Interval::new(0.001, f32::INFINITY),
+            )
+            .is_none()
+        {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * std::f32::consts::PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let u = sampler.get_2d();
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return sample_unit_vector(u);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + u[0] * (cos_theta_max - 1.0);
+        let phi = 2.0 * std::f32::consts::PI * u[1];
+        let sin_theta = (1.0 - z * z).sqrt();
+        Onb::new(direction).transform(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian, sampler::IndependentSampler, utils::Rng, vec3::random_unit_vector,
+    };
+
+    #[test]
+    fn maps_spherical_uv() {
+        let uv = |x: f32, y: f32, z: f32| Sphere::uv(Point3::new(x, y, z));
+        let close = |(u, v): (f32, f32), (eu, ev): (f32, f32)| {
+            assert!((u - eu).abs() < 1e-5 && (v - ev).abs() < 1e-5, "({u}, {v})");
+        };
+        close(uv(1.0, 0.0, 0.0), (0.5, 0.5));
+        // u is arbitrary at the poles
+        assert!((uv(0.0, 1.0, 0.0).1 - 1.0).abs() < 1e-5);
+        assert!(uv(0.0, -1.0, 0.0).1.abs() < 1e-5);
+        close(uv(-1.0, 0.0, 0.0), (0.0, 0.5));
+        close(uv(0.0, 0.0, 1.0), (0.25, 0.5));
+        close(uv(0.0, 0.0, -1.0), (0.75, 0.5));
+
+        // hit() fills in the same coordinates
+        let sphere = Sphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            2.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere
+            .hit(&ray, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        close((hit.u, hit.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn moving_sphere_follows_the_ray_time() {
+        let sphere = Sphere::moving(
+            Point3::new(0.0, 0.0, -3.0),
+            Point3::new(2.0, 0.0, -3.0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let all = Interval::new(0.001, f32::INFINITY);
+        let ray_at = |x: f32, time: f32| {
+            Ray::new(Point3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)).with_time(time)
+        };
+        assert!(sphere.hit(&ray_at(0.0, 0.0), all).is_some());
+        assert!(sphere.hit(&ray_at(0.0, 1.0), all).is_none());
+        assert!(sphere.hit(&ray_at(2.0, 1.0), all).is_some());
+
+        // halfway along, the normal is taken from the moved center
+        let hit = sphere.hit(&ray_at(1.3, 0.5), all).unwrap();
+        assert!((hit.normal_vec.x - 0.6).abs() < 1e-5);
+
+        // the box covers the whole path
+        let bbox = sphere.bounding_box();
+        assert!((bbox.x.min + 0.5).abs() < 1e-5 && (bbox.x.max - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn light_pdf_integrates_to_one() {
+        let sphere = Sphere::new(
+            Point3::new(1.0, 3.0, -2.0),
+            1.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let mut sampler = IndependentSampler::new(1);
+        let mut rng = Rng::new(2);
+        for origin in [Point3::zero(), Point3::new(1.5, 3.0, -2.0)] {
+            for _ in 0..100 {
+                let direction = sphere.random(origin, &mut sampler);
+                assert!(sphere.pdf_value(origin, direction) > 0.0);
+            }
+            let trials = 200_000;
+            let sum: f32 = (0..trials)
+                .map(|_| sphere.pdf_value(origin, random_unit_vector(&mut rng)))
+                .sum();
+            let integral = sum / trials as f32 * 4.0 * std::f32::consts::PI;
+            assert!((integral - 1.0).abs() < 0.05, "{origin:?}: {integral}");
+        }
+    }
 }
 
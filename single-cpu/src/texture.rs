@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use crate::{color::Color, image::Image, perlin::Perlin, vec3::Point3};
+
+/// Surface colour looked up by the hit's (u, v) coordinates or its point
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    pub albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        self.albedo
+    }
+}
+
+/// 空間を一辺 `scale` の立方体に区切って、2 つの texture を交互に並べる
+pub struct Checker {
+    inv_scale: f32,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    pub fn new(scale: f32, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f32, even: Color, odd: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color {
+        let cell = |x: f32| (self.inv_scale * x).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Image mapped over (u, v) in [0, 1], repeating outside of it
+///
+/// v = 0 is the bottom row of the image, as in OBJ texture coordinates.
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: Point3) -> Color {
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+        let x = ((u * self.image.width as f32) as u32).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as u32).min(self.image.height - 1);
+        self.image.pixel(x, y)
+    }
+}
+
+/// octaves used by the turbulence of the procedural textures
+const TURBULENCE_DEPTH: u32 = 7;
+
+/// Grey fBm turbulence, `scale` is the feature frequency
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f32,
+}
+
+impl NoiseTexture {
+    pub fn new(noise: Perlin, scale: f32) -> Self {
+        Self { noise, scale }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let t = self.noise.turbulence(self.scale * p, TURBULENCE_DEPTH);
+        Color::new(1.0, 1.0, 1.0) * t.min(1.0)
+    }
+}
+
+/// z 方向の縞を turbulence で揺らした大理石模様
+pub struct Marble {
+    noise: Perlin,
+    scale: f32,
+    pub color: Color,
+}
+
+impl Marble {
+    pub fn new(noise: Perlin, scale: f32, color: Color) -> Self {
+        Self {
+            noise,
+            scale,
+            color,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let phase = self.scale * p.z + 10.0 * self.noise.turbulence(p, TURBULENCE_DEPTH);
+        self.color * (0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// Growth rings around the y axis, `scale` rings per unit of radius,
+/// shading from `light` to `dark` within each ring
+pub struct Wood {
+    noise: Perlin,
+    scale: f32,
+    pub light: Color,
+    pub dark: Color,
+}
+
+impl Wood {
+    pub fn new(noise: Perlin, scale: f32, light: Color, dark: Color) -> Self {
+        Self {
+            noise,
+            scale,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let radius = (p.x * p.x + p.z * p.z).sqrt();
+        let rings = self.scale * radius + 0.5 * self.noise.turbulence(p, TURBULENCE_DEPTH);
+        let t = rings - rings.floor();
+        (1.0 - t) * self.light + t * self.dark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_in_3d() {
+        let checker = Checker::from_colors(0.5, Color::new(1.0, 1.0, 1.0), Color::zero());
+        let at = |x: f32, y: f32, z: f32| checker.value(0.0, 0.0, Point3::new(x, y, z)).x;
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.6, 0.6), 0.0);
+        // cells continue across the origin
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.0);
+        assert_eq!(at(-0.1, -0.1, -0.1), 0.0);
+    }
+
+    #[test]
+    fn image_texture_maps_v_up_and_repeats() {
+        // 2x2: red, green on top; blue, white below
+        let image = Image::new(
+            2,
+            2,
+            vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+            ],
+        );
+        let texture = ImageTexture::new(image);
+        let at = |u: f32, v: f32| texture.value(u, v, Point3::zero());
+        assert_eq!(at(0.25, 0.75).x, 1.0);
+        assert_eq!(at(0.75, 0.75).y, 1.0);
+        assert_eq!(at(0.25, 0.25).z, 1.0);
+        assert_eq!(at(1.0, 1.0).x, 0.0); // wraps to (0, 0), blue
+        assert_eq!(at(-0.25, 1.25).y, 1.0);
+    }
+
+    #[test]
+    fn procedural_textures_stay_in_their_palette() {
+        let marble = Marble::new(Perlin::new(3), 4.0, Color::new(1.0, 0.5, 0.25));
+        let light = Color::new(0.8, 0.6, 0.4);
+        let dark = Color::new(0.3, 0.15, 0.05);
+        let wood = Wood::new(Perlin::new(3), 6.0, light, dark);
+        let noise = NoiseTexture::new(Perlin::new(3), 2.0);
+        for i in 0..200 {
+            let t = i as f32 * 0.173;
+            let p = Point3::new(t.sin() * 3.0, t * 0.2, t.cos() * 3.0);
+
+            let m = marble.value(0.0, 0.0, p);
+            assert!((0.0..=1.0).contains(&m.x));
+            assert!((m.y - 0.5 * m.x).abs() < 1e-5 && (m.z - 0.25 * m.x).abs() < 1e-5);
+
+            // a mix of the two ring colours
+            let w = wood.value(0.0, 0.0, p);
+            let s = (w.x - light.x) / (dark.x - light.x);
+            assert!((0.0..=1.0).contains(&s));
+            assert!((w.z - (light.z + s * (dark.z - light.z))).abs() < 1e-5);
+
+            let n = noise.value(0.0, 0.0, p);
+            assert!((0.0..=1.0).contains(&n.x) && n.x == n.y);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Display transform: exposure and a tone mapping curve that bring linear
+//! scene radiance into the [0, 1] range the 8/16-bit writers quantize.
+//! The floating point formats skip this stage and keep the raw radiance.
+
+use crate::color::Color;
+
+/// default white point of the extended Reinhard curve
+const DEFAULT_WHITE_POINT: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// clamp to [0, 1], no curve
+    Clamp,
+    /// x / (1 + x)
+    Reinhard,
+    /// Reinhard that maps `white` (and anything brighter) to 1
+    ReinhardExtended { white: f32 },
+    /// John Hable's Uncharted 2 filmic curve
+    Hable,
+    /// Stephen Hill's fit of the ACES RRT + sRGB ODT
+    Aces,
+}
+
+impl ToneMap {
+    pub const NAMES: &str = "clamp, reinhard, reinhard-extended, hable, aces";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "reinhard-extended" => Some(ToneMap::ReinhardExtended {
+                white: DEFAULT_WHITE_POINT,
+            }),
+            "hable" | "filmic" => Some(ToneMap::Hable),
+            "aces" => Some(ToneMap::Aces),
+            _ => None,
+        }
+    }
+
+    /// 各チャンネルに曲線をかける。結果は [0, 1]
+    pub fn apply(&self, color: Color) -> Color {
+        let per_channel = |f: fn(f32) -> f32| Color::new(f(color.x), f(color.y), f(color.z));
+        let mapped = match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => per_channel(|x| x / (1.0 + x)),
+            ToneMap::ReinhardExtended { white } => {
+                let curve = |x: f32| x * (1.0 + x / (white * white)) / (1.0 + x);
+                Color::new(curve(color.x), curve(color.y), curve(color.z))
+            }
+            ToneMap::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                per_channel(|x| hable_partial(x * EXPOSURE_BIAS) / hable_partial(WHITE))
+            }
+            ToneMap::Aces => aces_fitted(color),
+        };
+        Color::new(
+            mapped.x.clamp(0.0, 1.0),
+            mapped.y.clamp(0.0, 1.0),
+            mapped.z.clamp(0.0, 1.0),
+        )
+    }
+}
+
+fn hable_partial(x: f32) -> f32 {
+    const A: f32 = 0.15; // shoulder strength
+    const B: f32 = 0.50; // linear strength
+    const C: f32 = 0.10; // linear angle
+    const D: f32 = 0.20; // toe strength
+    const E: f32 = 0.02; // toe numerator
+    const F: f32 = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+fn aces_fitted(color: Color) -> Color {
+    // sRGB -> ACES AP1 with the RRT saturation folded in, and back
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let mul = |m: &[[f32; 3]; 3], c: Color| {
+        Color::new(
+            m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+            m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+            m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+        )
+    };
+    let rrt_and_odt = |v: f32| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.432951) + 0.238081;
+        a / b
+    };
+    let c = mul(&INPUT, color);
+    mul(
+        &OUTPUT,
+        Color::new(rrt_and_odt(c.x), rrt_and_odt(c.y), rrt_and_odt(c.z)),
+    )
+}
+
+/// exposure (EV) をかけてから tone map する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayTransform {
+    /// stops, radiance is scaled by 2^exposure
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// linear radiance -> display linear [0, 1]
+    pub fn apply(&self, radiance: Color) -> Color {
+        self.tone_map.apply(radiance * 2f32.powf(self.exposure))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ReinhardExtended { white: 4.0 },
+        ToneMap::Hable,
+        ToneMap::Aces,
+    ];
+
+    #[test]
+    fn curves_are_monotonic_and_bounded() {
+        for tone_map in OPERATORS {
+            let black = tone_map.apply(Color::zero());
+            assert!(black.x.abs() < 1e-3, "{tone_map:?} maps black to {black:?}");
+
+            let mut previous = -1.0;
+            for i in 0..=200 {
+                let x = i as f32 * 0.1;
+                let y = tone_map.apply(Color::new(x, x, x)).y;
+                assert!((0.0..=1.0).contains(&y), "{tone_map:?}({x}) = {y}");
+                assert!(y >= previous, "{tone_map:?} decreases at {x}");
+                previous = y;
+            }
+        }
+    }
+
+    #[test]
+    fn curves_hit_their_reference_points() {
+        let grey = |tone_map: ToneMap, x: f32| tone_map.apply(Color::new(x, x, x)).x;
+        assert_eq!(grey(ToneMap::Reinhard, 1.0), 0.5);
+        assert!((grey(ToneMap::ReinhardExtended { white: 4.0 }, 4.0) - 1.0).abs() < 1e-6);
+        // the Hable curve is normalized so its white point maps to 1
+        assert!((grey(ToneMap::Hable, 11.2 / 2.0) - 1.0).abs() < 1e-5);
+        // like the reference RRT + ODT, the fit puts mid grey near 0.1
+        let aces = grey(ToneMap::Aces, 0.18);
+        assert!((0.09..0.12).contains(&aces), "{aces}");
+    }
+
+    #[test]
+    fn exposure_scales_by_stops() {
+        let display = DisplayTransform {
+            exposure: 2.0,
+            tone_map: ToneMap::Clamp,
+        };
+        assert_eq!(display.apply(Color::new(0.1, 0.2, 0.05)).y, 0.8);
+        assert_eq!(
+            DisplayTransform::default()
+                .apply(Color::new(2.0, 0.5, -1.0))
+                .x,
+            1.0
+        );
+    }
+}
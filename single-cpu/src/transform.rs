@@ -0,0 +1,228 @@
+use crate::{
+    aabb::Aabb,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+/// Affine transform, a 4x4 matrix kept together with its inverse
+///
+/// The builders append to the transform, so
+/// `Transform::identity().scale(..).rotate_y(..).translate(..)` scales
+/// first and translates last.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    /// self の後に (matrix, inverse) をかける
+    fn then(&self, matrix: Matrix, inverse: Matrix) -> Self {
+        Self {
+            matrix: multiply(&matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &inverse),
+        }
+    }
+
+    pub fn translate(&self, offset: Vec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][3] = offset[axis];
+            inverse[axis][3] = -offset[axis];
+        }
+        self.then(matrix, inverse)
+    }
+
+    /// 各軸ごとの拡大率。0 は逆行列が無いので不可
+    pub fn scale(&self, factor: Vec3) -> Self {
+        assert!(
+            factor.x != 0.0 && factor.y != 0.0 && factor.z != 0.0,
+            "scale factor must not be zero"
+        );
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][axis] = factor[axis];
+            inverse[axis][axis] = 1.0 / factor[axis];
+        }
+        self.then(matrix, inverse)
+    }
+
+    /// rotation by `degrees` around `axis`, counter-clockwise when looking
+    /// down the axis towards the origin
+    pub fn rotate(&self, axis: Vec3, degrees: f32) -> Self {
+        let a = unit_vector(axis);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let matrix = [
+            [
+                t * a.x * a.x + cos,
+                t * a.x * a.y - sin * a.z,
+                t * a.x * a.z + sin * a.y,
+                0.0,
+            ],
+            [
+                t * a.x * a.y + sin * a.z,
+                t * a.y * a.y + cos,
+                t * a.y * a.z - sin * a.x,
+                0.0,
+            ],
+            [
+                t * a.x * a.z - sin * a.y,
+                t * a.y * a.z + sin * a.x,
+                t * a.z * a.z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        // rotations are orthogonal, the inverse is the transpose
+        let mut inverse = IDENTITY;
+        for (i, row) in inverse.iter_mut().enumerate().take(3) {
+            for (j, value) in row.iter_mut().enumerate().take(3) {
+                *value = matrix[j][i];
+            }
+        }
+        self.then(matrix, inverse)
+    }
+
+    pub fn rotate_x(&self, degrees: f32) -> Self {
+        self.rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(&self, degrees: f32) -> Self {
+        self.rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(&self, degrees: f32) -> Self {
+        self.rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    fn apply(m: &Matrix, v: Vec3, w: f32) -> Vec3 {
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z + m[0][3] * w,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z + m[1][3] * w,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z + m[2][3] * w,
+        )
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        Self::apply(&self.matrix, p, 1.0)
+    }
+
+    /// 法線は逆行列の転置でかける。非一様な scale でも面に垂直なまま
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse;
+        unit_vector(Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        ))
+    }
+
+    pub fn inverse_point(&self, p: Point3) -> Point3 {
+        Self::apply(&self.inverse, p, 1.0)
+    }
+
+    pub fn inverse_vector(&self, v: Vec3) -> Vec3 {
+        Self::apply(&self.inverse, v, 0.0)
+    }
+
+    /// 8 つの角を変換して、それを囲む箱
+    pub fn bbox(&self, bbox: Aabb) -> Aabb {
+        let mut result = Aabb::empty();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let p = self.point(corner);
+            result = Aabb::enclosing(result, Aabb::from_points(p, p));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn builders_apply_in_order() {
+        let transform = Transform::identity()
+            .scale(Vec3::new(2.0, 2.0, 2.0))
+            .rotate_y(90.0)
+            .translate(Vec3::new(10.0, 0.0, 0.0));
+        // (1, 0, 0) -> (2, 0, 0) -> (0, 0, -2) -> (10, 0, -2)
+        let p = transform.point(Point3::new(1.0, 0.0, 0.0));
+        assert_close(p, Point3::new(10.0, 0.0, -2.0));
+        assert_close(transform.inverse_point(p), Point3::new(1.0, 0.0, 0.0));
+        assert_close(
+            transform.inverse_vector(Vec3::new(0.0, 2.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        let tilted = Transform::identity()
+            .rotate_x(30.0)
+            .rotate_z(-70.0)
+            .translate(Vec3::new(1.0, 2.0, 3.0));
+        let q = Point3::new(0.3, -1.0, 2.0);
+        assert_close(tilted.inverse_point(tilted.point(q)), q);
+        // rotations keep lengths
+        assert!(
+            ((tilted.point(q) - tilted.point(Point3::zero())).length() - q.length()).abs() < 1e-4
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::identity()
+            .rotate(Vec3::new(1.0, 1.0, 0.0), 40.0)
+            .scale(Vec3::new(3.0, 0.5, 1.0));
+        // plane spanned by a and b, with normal n
+        let a = Vec3::new(1.0, 1.0, 0.0);
+        let b = Vec3::new(0.0, 1.0, 1.0);
+        let n = a.cross(b);
+        let normal = transform.normal(n);
+        let origin = transform.point(Point3::zero());
+        assert!((normal.length() - 1.0).abs() < 1e-5);
+        assert!(normal.dot(transform.point(a) - origin).abs() < 1e-5);
+        assert!(normal.dot(transform.point(b) - origin).abs() < 1e-5);
+    }
+}
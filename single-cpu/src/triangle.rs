@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit_record::{HitRecord, Hittable, HittableList},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    vec3::{unit_vector, Point3, Vec3},
+};
+
+/// Möller–Trumbore intersection. Returns (t, b1, b2) where b1, b2 are the
+/// barycentric weights of p1 and p2.
+fn intersect(
+    r: &Ray,
+    interval: Interval,
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = r.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    // ray is parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = r.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if !interval.surrounds(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn triangle_bbox(p0: Point3, p1: Point3, p2: Point3) -> Aabb {
+    Aabb::enclosing(Aabb::from_points(p0, p1), Aabb::from_points(p2, p2))
+}
+
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    pub material: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices: [p0, p1, p2],
+            material,
+            bbox: triangle_bbox(p0, p1, p2),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.vertices;
+        let (t, b1, b2) = intersect(r, interval, p0, p1, p2)?;
+
+        let mut record = HitRecord::new(r.at(t), Vec3::zero(), t, false);
+        record.u = b1;
+        record.v = b2;
+        record.material = Some(self.material.clone());
+        record.set_face_normal_vec(r, unit_vector((p1 - p0).cross(p2 - p0)));
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+/// One face of a `TriangleMesh`. Each attribute is indexed separately, like
+/// OBJ faces, so vertices can share positions while having different normals.
+#[derive(Clone, Copy)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    /// index into `TriangleMesh::materials`
+    pub material: usize,
+}
+
+/// Indexed triangle mesh whose vertex buffers are shared by all its triangles
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub faces: Vec<MeshFace>,
+    pub materials: Vec<Arc<dyn Material>>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        faces: Vec<MeshFace>,
+        materials: Vec<Arc<dyn Material>>,
+    ) -> Self {
+        for face in &faces {
+            assert!(face.positions.iter().all(|&i| i < positions.len()));
+            assert!(face
+                .normals
+                .is_none_or(|n| n.iter().all(|&i| i < normals.len())));
+            assert!(face.uvs.is_none_or(|uv| uv.iter().all(|&i| i < uvs.len())));
+            assert!(face.material < materials.len());
+        }
+        Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+            materials,
+        }
+    }
+
+    /// 全ての三角形を list にする。BvhNode::new に渡すとよい
+    pub fn into_list(self) -> HittableList {
+        let mesh = Arc::new(self);
+        let mut list = HittableList::new();
+        for face in 0..mesh.faces.len() {
+            list.add(Box::new(MeshTriangle::new(mesh.clone(), face)));
+        }
+        list
+    }
+}
+
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+    bbox: Aabb,
+}
+
+impl MeshTriangle {
+    pub fn new(mesh: Arc<TriangleMesh>, face: usize) -> Self {
+        let [p0, p1, p2] = mesh.faces[face].positions.map(|i| mesh.positions[i]);
+        Self {
+            bbox: triangle_bbox(p0, p1, p2),
+            mesh,
+            face,
+        }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<HitRecord> {
+        let face = &self.mesh.faces[self.face];
+        let [p0, p1, p2] = face.positions.map(|i| self.mesh.positions[i]);
+        let (t, b1, b2) = intersect(r, interval, p0, p1, p2)?;
+        let b0 = 1.0 - b1 - b2;
+
+        let mut record = HitRecord::new(r.at(t), Vec3::zero(), t, false);
+        record.material = Some(self.mesh.materials[face.material].clone());
+        record.set_face_normal_vec(r, unit_vector((p1 - p0).cross(p2 - p0)));
+
+        if let Some(normals) = face.normals {
+            let [n0, n1, n2] = normals.map(|i| self.mesh.normals[i]);
+            let shading_normal_vec = b0 * n0 + b1 * n1 + b2 * n2;
+            if !shading_normal_vec.near_zero() {
+                record.set_shading_normal_vec(unit_vector(shading_normal_vec));
+            }
+        }
+
+        (record.u, record.v) = match face.uvs {
+            Some(uvs) => {
+                let [uv0, uv1, uv2] = uvs.map(|i| self.mesh.uvs[i]);
+                (
+                    b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                    b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+                )
+            }
+            None => (b1, b2),
+        };
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn triangle_hit_and_miss() {
+        let triangle = Triangle::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            material(),
+        );
+        let interval = Interval::new(0.001, f32::INFINITY);
+
+        let hit = triangle
+            .hit(
+                &Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0)),
+                interval,
+            )
+            .expect("ray through the centre should hit");
+        assert!((hit.t - 2.0).abs() < 1e-5);
+        assert!(hit.front_face);
+        assert!((hit.normal_vec.z - 1.0).abs() < 1e-5);
+
+        let miss = triangle.hit(
+            &Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+            interval,
+        );
+        assert!(miss.is_none());
+    }
+
+    #[test]
+    fn mesh_interpolates_vertex_normals() {
+        let mesh = TriangleMesh::new(
+            vec![
+                Point3::new(-1.0, -1.0, -2.0),
+                Point3::new(1.0, -1.0, -2.0),
+                Point3::new(0.0, 1.0, -2.0),
+            ],
+            vec![
+                unit_vector(Vec3::new(-1.0, 0.0, 1.0)),
+                unit_vector(Vec3::new(1.0, 0.0, 1.0)),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            vec![(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)],
+            vec![MeshFace {
+                positions: [0, 1, 2],
+                normals: Some([0, 1, 2]),
+                uvs: Some([0, 1, 2]),
+                material: 0,
+            }],
+            vec![material()],
+        );
+        let list = mesh.into_list();
+
+        // point on the edge between vertex 0 and 1, halfway
+        let hit = list
+            .hit(
+                &Ray::new(
+                    Point3::new(0.0, -1.0 + 1e-4, 0.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                ),
+                Interval::new(0.001, f32::INFINITY),
+            )
+            .unwrap();
+        assert!(hit.normal_vec.x.abs() < 1e-3);
+        assert!((hit.normal_vec.length() - 1.0).abs() < 1e-5);
+        assert!((hit.u - 0.5).abs() < 1e-3);
+        assert!(hit.v.abs() < 1e-3);
+
+        // near vertex 1 the normal leans towards +x
+        let hit = list
+            .hit(
+                &Ray::new(Point3::new(0.9, -0.95, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+                Interval::new(0.001, f32::INFINITY),
+            )
+            .unwrap();
+        assert!(hit.normal_vec.x > 0.5);
+    }
+}
@@ -1,20 +1,80 @@
-use std::sync::Mutex;
+/// seed と stream 番号から独立した seed を作る (splitmix64)
+pub fn mix_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// 値の並びを 1 つの seed にまとめる
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x5eed, |h, &v| mix_seed(h, v))
+}
+
+/// Counter-based random numbers: value `n` of a stream is a hash of the
+/// stream's key and `n`, so nothing is shared between threads and every
+/// value can be recomputed from where it is used
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    key: u64,
+    counter: u64,
+}
 
-use once_cell::sync::Lazy;
-use rand::{distr::Uniform, rngs::StdRng, Rng, SeedableRng};
+impl Rng {
+    pub fn new(key: u64) -> Self {
+        Self { key, counter: 0 }
+    }
 
-// static な分布と生成器を 1 回だけ作って、毎回それを使用
-static RNG: Lazy<Mutex<StdRng>> = Lazy::new(|| Mutex::new(StdRng::seed_from_u64(5464)));
-static DIST: Lazy<Uniform<f32>> = Lazy::new(|| Uniform::new(0.0, 1.0).unwrap());
+    /// the stream of sample `index` of pixel (x, y); the counter then
+    /// walks the dimensions
+    pub fn for_sample(seed: u64, x: u32, y: u32, index: u32) -> Self {
+        Self::new(hash(&[seed, x as u64, y as u64, index as u64]))
+    }
 
-#[inline]
-pub fn f32_random() -> f32 {
-    let mut rng = RNG.lock().unwrap();
-    rng.sample(*DIST)
+    pub fn next_u64(&mut self) -> u64 {
+        self.counter += 1;
+        mix_seed(self.key, self.counter - 1)
+    }
+
+    /// [0, 1) from the top 24 bits
+    #[inline]
+    pub fn f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    #[inline]
+    pub fn f32_range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.f32()
+    }
 }
 
-#[inline]
-pub fn f32_random_range(min: f32, max: f32) -> f32 {
-    min + (max - min) * f32_random()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_are_reproducible_and_independent() {
+        let draw = |mut rng: Rng| (0..1000).map(|_| rng.f32()).collect::<Vec<_>>();
+        let a = draw(Rng::for_sample(1, 2, 3, 4));
+        assert_eq!(a, draw(Rng::for_sample(1, 2, 3, 4)));
+
+        // neighbouring pixels, samples and seeds share no values
+        for other in [
+            Rng::for_sample(1, 3, 3, 4),
+            Rng::for_sample(1, 2, 3, 5),
+            Rng::for_sample(2, 2, 3, 4),
+        ] {
+            let b = draw(other);
+            assert!(a.iter().zip(&b).all(|(x, y)| x != y));
+        }
+
+        // uniform on [0, 1): mean 1/2, variance 1/12
+        let values = draw(Rng::new(7));
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        let mean = values.iter().sum::<f32>() / 1000.0;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / 1000.0;
+        assert!((mean - 0.5).abs() < 0.03, "{mean}");
+        assert!((variance - 1.0 / 12.0).abs() < 0.01, "{variance}");
+    }
 }
 
@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
 
-use crate::utils::{f32_random, f32_random_range};
+use crate::utils::Rng;
 
 #[derive(Clone, Copy)]
 pub struct Vec3 {
@@ -33,15 +33,15 @@ impl Vec3 {
         let s = 1e-8;
         f32::abs(self.x) < s && f32::abs(self.y) < s && f32::abs(self.z) < s
     }
-    pub fn random() -> Self {
-        Self::new(f32_random(), f32_random(), f32_random())
+    pub fn random(rng: &mut Rng) -> Self {
+        Self::new(rng.f32(), rng.f32(), rng.f32())
     }
 
-    pub fn random_range(min: f32, max: f32) -> Self {
+    pub fn random_range(rng: &mut Rng, min: f32, max: f32) -> Self {
         Self::new(
-            f32_random_range(min, max),
-            f32_random_range(min, max),
-            f32_random_range(min, max),
+            rng.f32_range(min, max),
+            rng.f32_range(min, max),
+            rng.f32_range(min, max),
         )
     }
 
@@ -75,24 +75,38 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
+/// maps a uniform point of [0, 1)^2 onto the unit disk (z = 0)
+///
+/// Concentric mapping (Shirley and Chiu): squares around the center
+/// become rings, so stratified points stay stratified on the disk.
 #[inline]
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            f32_random_range(-1.0, 1.0),
-            f32_random_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
+pub fn sample_unit_disk(u: [f32; 2]) -> Vec3 {
+    let (a, b) = (2.0 * u[0] - 1.0, 2.0 * u[1] - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let quarter = std::f32::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter * (b / a))
+    } else {
+        (b, 2.0 * quarter - quarter * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// maps a uniform point of [0, 1)^2 onto the unit sphere, area preserving
+#[inline]
+pub fn sample_unit_vector(u: [f32; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u[0];
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
 #[inline]
-pub fn random_unit_vector() -> Vec3 {
+pub fn random_unit_vector(rng: &mut Rng) -> Vec3 {
     loop {
-        let p = Vec3::random_range(-1.0, 1.0);
+        let p = Vec3::random_range(rng, -1.0, 1.0);
         let lensq = p.length_squared();
         if lensq <= 1.0 && 1e-12 < lensq {
             return p / lensq.sqrt();
@@ -100,9 +114,10 @@ pub fn random_unit_vector() -> Vec3 {
     }
 }
 
+#[allow(dead_code)]
 #[inline]
-pub fn random_on_hemisphere(normal_vec: Vec3) -> Vec3 {
-    let on_unit_sphere = random_unit_vector();
+pub fn random_on_hemisphere(normal_vec: Vec3, rng: &mut Rng) -> Vec3 {
+    let on_unit_sphere = random_unit_vector(rng);
     if on_unit_sphere.dot(normal_vec) > 0.0 {
         // same hemisphere as normal vector
         on_unit_sphere
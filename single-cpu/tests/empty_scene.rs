@@ -0,0 +1,17 @@
+use std::{fs, process::Command};
+
+/// a scene file without `[[objects]]` is an error, not a panic
+#[test]
+fn empty_scene_is_an_error() {
+    let path = std::env::temp_dir().join(format!("empty-scene-{}.toml", std::process::id()));
+    fs::write(&path, "[camera]\nimage_width = 8\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_single-cpu"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("scene has no objects"), "{stderr}");
+}